@@ -0,0 +1,103 @@
+use crate::permutation::Permutation;
+
+/// Which move may follow which in a canonical move sequence.
+///
+/// Moves are referred to by their index in `PuzzleTrait::all_moves`. Everything
+/// is derived from the moves' slot tables (`table[i]` is the slot whose piece
+/// the move brings into slot `i`):
+/// - a move with the same table as an earlier one is never used;
+/// - moves that turn the same slots belong to the same face, and a face may
+///   only be turned by repeating the same move, so `X CW; X CCW;` never occurs;
+/// - a run of the same move is cut off once the opposite direction reaches the
+///   same state in fewer moves (on a tie the lower move index keeps the run);
+/// - of two faces whose moves commute, the one with the lower index has to
+///   come first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoveRules {
+    face_of: Vec<usize>,
+    max_run: Vec<usize>,
+    /// `must_precede[a][b]`: a move of face `a` may not directly follow one of face `b`.
+    must_precede: Vec<Vec<bool>>,
+}
+
+/// The move applied last and how often it was repeated in a row.
+pub type LastMove = Option<(usize, usize)>;
+
+impl MoveRules {
+    /// Allows every sequence, for puzzles that cannot describe their moves as tables.
+    pub fn unrestricted(move_count: usize) -> Self {
+        Self {
+            face_of: (0..move_count).collect(),
+            max_run: vec![usize::MAX; move_count],
+            must_precede: vec![vec![false; move_count]; move_count],
+        }
+    }
+
+    pub fn from_tables(tables: &[Permutation]) -> Self {
+        let moved: Vec<Vec<u8>> = tables.iter().map(Permutation::moved_slots).collect();
+        let mut face_of = Vec::with_capacity(tables.len());
+        let mut face_moves: Vec<usize> = Vec::new();
+        for i in 0..tables.len() {
+            match face_moves
+                .iter()
+                .position(|&first| moved[first] == moved[i])
+            {
+                Some(face) => face_of.push(face),
+                None => {
+                    face_of.push(face_moves.len());
+                    face_moves.push(i);
+                }
+            }
+        }
+        let max_run = (0..tables.len())
+            .map(|i| {
+                if tables[..i].contains(&tables[i]) {
+                    return 0;
+                }
+                let order = tables[i].order();
+                let inverse = tables[i].inverse();
+                match tables.iter().position(|table| *table == inverse) {
+                    // k turns equal order - k turns of the inverse
+                    Some(j) if order.is_multiple_of(2) && i < j => order / 2,
+                    Some(_) => (order - 1) / 2,
+                    None => order - 1,
+                }
+                .max(1)
+            })
+            .collect();
+        let faces = face_moves.len();
+        let mut must_precede = vec![vec![false; faces]; faces];
+        for a in 0..faces {
+            for b in a + 1..faces {
+                let table_a = &tables[face_moves[a]];
+                let table_b = &tables[face_moves[b]];
+                if table_a.compose(table_b) == table_b.compose(table_a) {
+                    must_precede[a][b] = true;
+                }
+            }
+        }
+        Self {
+            face_of,
+            max_run,
+            must_precede,
+        }
+    }
+
+    /// Returns the run length after appending move `next`, or `None` if the
+    /// resulting sequence is not canonical.
+    pub fn next_run(&self, last: LastMove, next: usize) -> Option<usize> {
+        if self.max_run[next] == 0 {
+            return None;
+        }
+        let Some((last, run)) = last else {
+            return Some(1);
+        };
+        if self.face_of[last] == self.face_of[next] {
+            return (last == next && run < self.max_run[next]).then_some(run + 1);
+        }
+        if self.must_precede[self.face_of[next]][self.face_of[last]] {
+            return None;
+        }
+        Some(1)
+    }
+}
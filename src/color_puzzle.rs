@@ -0,0 +1,184 @@
+use crate::error::SolverError;
+use crate::helpers::read_record_part;
+use crate::packed::{
+    pack, packed_from_bytes, packed_to_bytes, unpack, COLOR_BITS, PACKED_COLORS_BYTES,
+};
+use crate::permutation::Permutation;
+use crate::puzzle_trait::PuzzleTrait;
+use crate::scramble::Scramble;
+use crate::single_puzzle::{
+    builtin_move_tables, score_colors, slot_color, validate_colors, validate_slots, Move,
+};
+
+/// The 24-slot puzzle reduced to its colors: pieces of the same color are
+/// indistinguishable, so states that only differ in which of them sits where
+/// are the same state. Like `SinglePuzzle`, whether moves also turn the
+/// opposite face is its config and not part of the state.
+#[derive(Clone)]
+pub struct ColorPuzzle {
+    /// 24 colors of `COLOR_BITS` each, slot 0 in the most significant position.
+    pub colors: u128,
+    /// `calculate_score` of `colors`, kept up to date by `apply_move`.
+    pub score: u8,
+}
+
+impl std::fmt::Debug for ColorPuzzle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ColorPuzzle")
+            .field("colors", &self.colors())
+            .field("score", &self.score)
+            .finish()
+    }
+}
+
+impl PartialEq for ColorPuzzle {
+    fn eq(&self, other: &Self) -> bool {
+        self.colors == other.colors
+    }
+}
+
+impl Eq for ColorPuzzle {}
+
+impl std::hash::Hash for ColorPuzzle {
+    fn hash<H: std::hash::Hasher>(&self, hasher: &mut H) {
+        self.colors.hash(hasher);
+    }
+}
+
+impl PartialOrd for ColorPuzzle {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ColorPuzzle {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.colors.cmp(&other.colors)
+    }
+}
+
+impl ColorPuzzle {
+    pub fn new_solved() -> Self {
+        let colors = std::array::from_fn(|slot| slot_color(slot as u8));
+        Self {
+            colors: pack(&colors, COLOR_BITS),
+            score: score_colors(&colors) as u8,
+        }
+    }
+
+    pub fn new_scrambled(scramble: Scramble, with_opposite_move: bool) -> Self {
+        let mut puzzle = ColorPuzzle::new_solved();
+        puzzle.apply_scramble(scramble, &with_opposite_move);
+        puzzle
+    }
+
+    /// Checks that every color appears exactly four times.
+    pub fn from_colors(colors: [u8; 24]) -> Result<Self, SolverError> {
+        validate_colors(&colors)?;
+        Ok(Self {
+            colors: pack(&colors, COLOR_BITS),
+            score: score_colors(&colors) as u8,
+        })
+    }
+
+    /// Checks that `slots` is a permutation of the slot ids and keeps their colors.
+    pub fn from_slots(slots: [u8; 24]) -> Result<Self, SolverError> {
+        validate_slots(&slots)?;
+        Self::from_colors(slots.map(slot_color))
+    }
+
+    pub fn colors(&self) -> [u8; 24] {
+        unpack(self.colors, COLOR_BITS)
+    }
+
+    pub fn calculate_score(&self) -> i64 {
+        score_colors(&self.colors())
+    }
+}
+
+impl PuzzleTrait for ColorPuzzle {
+    type Move = Move;
+    /// Whether every move also turns the opposite face.
+    type Config = bool;
+
+    const TABLE_ID: &'static str = "colors";
+    const MOVE_BITS: u32 = 4;
+
+    fn encode_config(with_opposite_move: &bool) -> String {
+        crate::helpers::encode_opposite_move(*with_opposite_move).to_string()
+    }
+    fn solved(_with_opposite_move: &bool) -> Self {
+        ColorPuzzle::new_solved()
+    }
+
+    fn all_moves(_with_opposite_move: &bool) -> Vec<Move> {
+        crate::helpers::get_all_moves()
+    }
+    fn apply_move(&mut self, mv: Move, with_opposite_move: &bool) {
+        self.score = (self.score as i64 + mv.score_delta(self.colors, *with_opposite_move)) as u8;
+        let mut colors = self.colors();
+        mv.permutation(*with_opposite_move).apply(&mut colors);
+        self.colors = pack(&colors, COLOR_BITS);
+    }
+    fn invert_move(mv: Move) -> Move {
+        mv.get_inverted_move()
+    }
+    fn move_tables(with_opposite_move: &bool) -> Option<Vec<Permutation>> {
+        Some(builtin_move_tables(*with_opposite_move))
+    }
+    fn calculate_score(&self, _with_opposite_move: &bool) -> i64 {
+        self.calculate_score()
+    }
+    fn score(&self, _with_opposite_move: &bool) -> i64 {
+        self.score as i64
+    }
+    fn score_delta(&self, mv: Move, with_opposite_move: &bool) -> i64 {
+        mv.score_delta(self.colors, *with_opposite_move)
+    }
+    fn move_to_byte(mv: Move) -> u8 {
+        mv.to_byte()
+    }
+    fn move_from_byte(byte: u8, _with_opposite_move: &bool) -> Result<Move, SolverError> {
+        Move::from_byte(byte)
+    }
+    fn write_state(&self, writer: &mut impl std::io::Write) -> Result<(), SolverError> {
+        writer.write_all(&packed_to_bytes::<PACKED_COLORS_BYTES>(self.colors))?;
+        Ok(())
+    }
+    fn read_state(
+        reader: &mut impl std::io::Read,
+        _with_opposite_move: &bool,
+    ) -> Result<Self, SolverError>
+    where
+        Self: Sized,
+    {
+        let mut colors_buf = [0u8; PACKED_COLORS_BYTES];
+        read_record_part(reader, &mut colors_buf)?;
+        let colors = unpack(packed_from_bytes(&colors_buf), COLOR_BITS);
+        ColorPuzzle::from_colors(colors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn the_kept_score_matches_a_recalculation() {
+        let mut rng = ChaCha8Rng::seed_from_u64(9);
+        for with_opposite_move in [false, true] {
+            let moves = ColorPuzzle::all_moves(&with_opposite_move);
+            let mut puzzle = ColorPuzzle::new_solved();
+            for _ in 0..200 {
+                let mv = moves[rng.gen_range(0..moves.len())];
+                let expected =
+                    puzzle.score(&with_opposite_move) + puzzle.score_delta(mv, &with_opposite_move);
+                puzzle.apply_move(mv, &with_opposite_move);
+                assert_eq!(puzzle.score(&with_opposite_move), puzzle.calculate_score());
+                assert_eq!(puzzle.score(&with_opposite_move), expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum SolverError {
+    /// Reading or writing a batch file or the store directory failed.
+    Io(std::io::Error),
+    /// A batch file ended in the middle of a record or holds garbage.
+    CorruptBatch(String),
+    /// A puzzle state that can not exist, e.g. an unknown slot id.
+    InvalidState(String),
+    /// Search options the solver does not support, e.g. too many score weakens.
+    InvalidOptions(String),
+    /// A byte in a stored scramble that does not encode a face or direction.
+    InvalidMoveByte(u8),
+    /// A puzzle spec file that could not be parsed.
+    InvalidSpec(String),
+    /// A solved-side table that is missing, of another version, or built for
+    /// a different puzzle.
+    InvalidTable(String),
+    /// A solution found by the search that does not solve the puzzle when
+    /// replayed, with a dump of the states involved.
+    InvalidSolution(String),
+    /// A face, direction, move or scramble in text form that could not be
+    /// parsed; `column` counts characters from 1.
+    InvalidNotation { column: usize, reason: String },
+}
+
+impl fmt::Display for SolverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolverError::Io(err) => write!(f, "I/O error: {}", err),
+            SolverError::CorruptBatch(reason) => write!(f, "corrupt batch: {}", reason),
+            SolverError::InvalidState(reason) => write!(f, "invalid state: {}", reason),
+            SolverError::InvalidOptions(reason) => write!(f, "invalid options: {}", reason),
+            SolverError::InvalidMoveByte(byte) => write!(f, "invalid move byte: {:#04x}", byte),
+            SolverError::InvalidSpec(reason) => write!(f, "invalid puzzle spec: {}", reason),
+            SolverError::InvalidTable(reason) => write!(f, "invalid table: {}", reason),
+            SolverError::InvalidSolution(dump) => write!(f, "invalid solution: {}", dump),
+            SolverError::InvalidNotation { column, reason } => {
+                write!(f, "invalid notation at column {}: {}", column, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SolverError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SolverError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for SolverError {
+    fn from(err: std::io::Error) -> Self {
+        SolverError::Io(err)
+    }
+}
@@ -0,0 +1,416 @@
+use crate::error::SolverError;
+use crate::puzzle_trait::PuzzleTrait;
+use crate::record::Record;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fs::{remove_file, File};
+use std::io::{BufReader, BufWriter, Write};
+
+/// Reads the records of a run file one at a time.
+pub struct RunReader<Puzzle: PuzzleTrait> {
+    path: String,
+    reader: BufReader<File>,
+    config: Puzzle::Config,
+    record: usize,
+}
+
+impl<Puzzle: PuzzleTrait> RunReader<Puzzle> {
+    pub fn open(path: &str, config: &Puzzle::Config) -> Result<Self, SolverError> {
+        Ok(Self {
+            path: path.to_string(),
+            reader: BufReader::new(File::open(path)?),
+            config: config.clone(),
+            record: 0,
+        })
+    }
+
+    /// Returns `Ok(None)` once the file is exhausted.
+    pub fn next_record(&mut self) -> Result<Option<Record<Puzzle>>, SolverError> {
+        match Record::load_binary_from_file(&mut self.reader, &self.config) {
+            Ok(record) => {
+                self.record += 1;
+                Ok(record)
+            }
+            Err(SolverError::CorruptBatch(reason)) => Err(SolverError::CorruptBatch(format!(
+                "{}: record {}: {}",
+                self.path, self.record, reason
+            ))),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Writes a sorted sequence of records into files of at most `states_per_file`
+/// records each.
+struct RunWriter {
+    prefix: String,
+    states_per_file: usize,
+    files: Vec<String>,
+    writer: Option<BufWriter<File>>,
+    written: usize,
+}
+
+impl RunWriter {
+    fn new(prefix: String, states_per_file: usize) -> Self {
+        Self {
+            prefix,
+            states_per_file: states_per_file.max(1),
+            files: Vec::new(),
+            writer: None,
+            written: 0,
+        }
+    }
+
+    fn write<Puzzle: PuzzleTrait>(&mut self, record: &Record<Puzzle>) -> Result<(), SolverError> {
+        if self.writer.is_none() || self.written == self.states_per_file {
+            self.finish_file()?;
+            let path = format!("{}_{}.bin", self.prefix, self.files.len());
+            self.writer = Some(BufWriter::new(File::create(&path)?));
+            self.files.push(path);
+        }
+        if let Some(writer) = self.writer.as_mut() {
+            record.save_binary_to_file(writer)?;
+        }
+        self.written += 1;
+        Ok(())
+    }
+
+    fn finish_file(&mut self) -> Result<(), SolverError> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        self.written = 0;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Vec<String>, SolverError> {
+        self.finish_file()?;
+        Ok(self.files)
+    }
+}
+
+/// The head of a run in the merge heap, ordered by `Record::key` and then by
+/// run, so that of equal states the one with the fewest moves comes out first.
+struct HeapEntry<Puzzle: PuzzleTrait> {
+    record: Record<Puzzle>,
+    run: usize,
+}
+
+impl<Puzzle: PuzzleTrait> PartialEq for HeapEntry<Puzzle> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<Puzzle: PuzzleTrait> Eq for HeapEntry<Puzzle> {}
+
+impl<Puzzle: PuzzleTrait> PartialOrd for HeapEntry<Puzzle> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<Puzzle: PuzzleTrait> Ord for HeapEntry<Puzzle> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.record.key(), self.run).cmp(&(other.record.key(), other.run))
+    }
+}
+
+/// Merges the sorted `runs` with a heap and hands the records to `writer` in
+/// order. Of states that compare equal only the one with the shortest scramble
+/// is kept, as any of them joins with the same states of the other side.
+fn merge_runs<Puzzle: PuzzleTrait>(
+    runs: &[String],
+    config: &Puzzle::Config,
+    writer: &mut RunWriter,
+) -> Result<(), SolverError> {
+    let mut readers = runs
+        .iter()
+        .map(|path| RunReader::<Puzzle>::open(path, config))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut heap = BinaryHeap::with_capacity(readers.len());
+    for (run, reader) in readers.iter_mut().enumerate() {
+        if let Some(record) = reader.next_record()? {
+            heap.push(Reverse(HeapEntry { record, run }));
+        }
+    }
+    let mut last: Option<Puzzle> = None;
+    while let Some(Reverse(HeapEntry { record, run })) = heap.pop() {
+        if let Some(next) = readers[run].next_record()? {
+            heap.push(Reverse(HeapEntry { record: next, run }));
+        }
+        if last.as_ref() != Some(&record.state) {
+            writer.write(&record)?;
+            last = Some(record.state);
+        }
+    }
+    Ok(())
+}
+
+/// Sorts the states of the sorted `runs` into one sequence, merging at most
+/// `fan_in` runs at a time. The result is written to `{directory}/batch_N.bin`
+/// files of `states_per_file` states each, returned in order; the input runs
+/// and intermediate merges are removed.
+pub fn external_sort<Puzzle: PuzzleTrait>(
+    mut runs: Vec<String>,
+    directory: &str,
+    fan_in: usize,
+    states_per_file: usize,
+    config: &Puzzle::Config,
+) -> Result<Vec<String>, SolverError> {
+    let fan_in = fan_in.max(2);
+    let mut pass = 0;
+    while runs.len() > fan_in {
+        let mut merged = Vec::with_capacity(runs.len().div_ceil(fan_in));
+        for (group, group_runs) in runs.chunks(fan_in).enumerate() {
+            let mut writer = RunWriter::new(
+                format!("{}/merge_{}_{}", directory, pass, group),
+                usize::MAX,
+            );
+            merge_runs::<Puzzle>(group_runs, config, &mut writer)?;
+            merged.extend(writer.finish()?);
+            remove_runs(group_runs)?;
+        }
+        runs = merged;
+        pass += 1;
+    }
+    let mut writer = RunWriter::new(format!("{}/batch", directory), states_per_file);
+    merge_runs::<Puzzle>(&runs, config, &mut writer)?;
+    let files = writer.finish()?;
+    remove_runs(&runs)?;
+    Ok(files)
+}
+
+/// Streams the sorted `files` and keeps the states that appear in none of the
+/// sorted file lists in `excluded`. The result is written to
+/// `{directory}/states_N.bin` files of `states_per_file` states each; `files`
+/// are removed. Returns the new files and the number of states kept.
+pub fn subtract_sorted<Puzzle: PuzzleTrait>(
+    files: Vec<String>,
+    excluded: &[&[String]],
+    directory: &str,
+    states_per_file: usize,
+    config: &Puzzle::Config,
+) -> Result<(Vec<String>, usize), SolverError> {
+    let mut cursor = SortedCursor::<Puzzle>::open(&files, config)?;
+    let mut excluded = excluded
+        .iter()
+        .map(|files| SortedCursor::<Puzzle>::open(files, config))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut writer = RunWriter::new(format!("{}/states", directory), states_per_file);
+    let mut kept = 0;
+    while let Some(record) = cursor.advance()? {
+        let mut seen = false;
+        for other in excluded.iter_mut() {
+            while other.peek().is_some_and(|other| other.state < record.state) {
+                other.advance()?;
+            }
+            seen |= other
+                .peek()
+                .is_some_and(|other| other.state == record.state);
+        }
+        if !seen {
+            writer.write(&record)?;
+            kept += 1;
+        }
+    }
+    let kept_files = writer.finish()?;
+    remove_runs(&files)?;
+    Ok((kept_files, kept))
+}
+
+fn remove_runs(runs: &[String]) -> Result<(), SolverError> {
+    for run in runs {
+        remove_file(run)?;
+    }
+    Ok(())
+}
+
+/// Walks the records of sorted files in order, reading each file once and
+/// keeping only the current record in memory.
+pub struct SortedCursor<Puzzle: PuzzleTrait> {
+    files: Vec<String>,
+    next_file: usize,
+    config: Puzzle::Config,
+    reader: Option<RunReader<Puzzle>>,
+    head: Option<Record<Puzzle>>,
+}
+
+impl<Puzzle: PuzzleTrait> SortedCursor<Puzzle> {
+    pub fn open(files: &[String], config: &Puzzle::Config) -> Result<Self, SolverError> {
+        let mut cursor = Self {
+            files: files.to_vec(),
+            next_file: 0,
+            config: config.clone(),
+            reader: None,
+            head: None,
+        };
+        cursor.head = cursor.read_next()?;
+        Ok(cursor)
+    }
+
+    /// The record the cursor is on, `None` once every file is exhausted.
+    pub fn peek(&self) -> Option<&Record<Puzzle>> {
+        self.head.as_ref()
+    }
+
+    /// Returns the current record and moves on to the next one.
+    pub fn advance(&mut self) -> Result<Option<Record<Puzzle>>, SolverError> {
+        let next = self.read_next()?;
+        Ok(std::mem::replace(&mut self.head, next))
+    }
+
+    fn read_next(&mut self) -> Result<Option<Record<Puzzle>>, SolverError> {
+        loop {
+            if let Some(reader) = self.reader.as_mut() {
+                if let Some(record) = reader.next_record()? {
+                    return Ok(Some(record));
+                }
+                self.reader = None;
+            }
+            let Some(path) = self.files.get(self.next_file) else {
+                return Ok(None);
+            };
+            self.reader = Some(RunReader::open(path, &self.config)?);
+            self.next_file += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::PackedMoves;
+    use crate::single_puzzle::{Direction, Face, Move, SinglePuzzle};
+
+    const CONFIG: bool = false;
+
+    fn test_directory(name: &str) -> String {
+        let directory =
+            std::env::temp_dir().join(format!("cube-solver-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        directory.to_string_lossy().into_owned()
+    }
+
+    /// Distinct states, sorted.
+    fn states() -> Vec<SinglePuzzle> {
+        let mut states: Vec<SinglePuzzle> = Face::ALL
+            .into_iter()
+            .map(|face| {
+                let mut state = SinglePuzzle::solved(&CONFIG);
+                state.apply_move(Move::new(face, Direction::Clockwise), &CONFIG);
+                state
+            })
+            .collect();
+        states.sort();
+        states
+    }
+
+    fn record(state: &SinglePuzzle, moves: usize) -> Record<SinglePuzzle> {
+        Record {
+            moves: (0..moves).fold(PackedMoves::default(), |packed, _| {
+                let mv = Move::new(Face::Left, Direction::Clockwise);
+                packed
+                    .push(SinglePuzzle::move_to_byte(mv), SinglePuzzle::MOVE_BITS)
+                    .unwrap()
+            }),
+            ..Record::root(state.clone())
+        }
+    }
+
+    fn write_run(path: String, records: &[Record<SinglePuzzle>]) -> String {
+        let mut writer = RunWriter::new(path, usize::MAX);
+        for record in records {
+            writer.write(record).unwrap();
+        }
+        writer.finish().unwrap().remove(0)
+    }
+
+    fn read_all(files: &[String]) -> Vec<(SinglePuzzle, usize)> {
+        let mut cursor = SortedCursor::<SinglePuzzle>::open(files, &CONFIG).unwrap();
+        let mut records = Vec::new();
+        while let Some(record) = cursor.advance().unwrap() {
+            records.push((record.state, record.moves.len()));
+        }
+        records
+    }
+
+    #[test]
+    fn merging_keeps_the_shortest_scramble_of_each_state() {
+        let directory = test_directory("merge");
+        let states = states();
+        let runs = vec![
+            write_run(
+                format!("{}/run_0", directory),
+                &[
+                    record(&states[0], 3),
+                    record(&states[1], 1),
+                    record(&states[1], 2),
+                ],
+            ),
+            write_run(
+                format!("{}/run_1", directory),
+                &[record(&states[0], 2), record(&states[2], 4)],
+            ),
+            write_run(
+                format!("{}/run_2", directory),
+                &[record(&states[0], 1), record(&states[2], 3)],
+            ),
+        ];
+        // a fan-in of 2 takes an intermediate pass over the three runs
+        let files = external_sort::<SinglePuzzle>(runs.clone(), &directory, 2, 2, &CONFIG).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(
+            read_all(&files),
+            vec![
+                (states[0].clone(), 1),
+                (states[1].clone(), 1),
+                (states[2].clone(), 3)
+            ]
+        );
+        assert!(runs.iter().all(|run| !std::path::Path::new(run).exists()));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn subtracting_drops_the_states_of_every_excluded_list() {
+        let directory = test_directory("subtract");
+        let states = states();
+        let files = vec![write_run(
+            format!("{}/files", directory),
+            &[
+                record(&states[0], 1),
+                record(&states[1], 1),
+                record(&states[2], 1),
+                record(&states[4], 1),
+            ],
+        )];
+        let first = vec![write_run(
+            format!("{}/first", directory),
+            &[record(&states[1], 0)],
+        )];
+        let second = vec![write_run(
+            format!("{}/second", directory),
+            &[
+                record(&states[3], 0),
+                record(&states[4], 2),
+                record(&states[5], 0),
+            ],
+        )];
+        let (kept_files, kept) = subtract_sorted::<SinglePuzzle>(
+            files.clone(),
+            &[&first, &second],
+            &directory,
+            usize::MAX,
+            &CONFIG,
+        )
+        .unwrap();
+        assert_eq!(kept, 2);
+        assert_eq!(
+            read_all(&kept_files),
+            vec![(states[0].clone(), 1), (states[2].clone(), 1)]
+        );
+        assert!(!std::path::Path::new(&files[0]).exists());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,7 +1,8 @@
-use crate::single_puzzle::{Move, Face, Direction};
+use crate::error::SolverError;
+use crate::puzzle_spec::builtin_spec;
 use crate::scramble::Scramble;
-use rand::seq::SliceRandom;
-use rand::thread_rng;
+use crate::scramble_generator::{random_seed, ScrambleGenerator};
+use crate::single_puzzle::{Direction, Face, Move};
 
 pub fn to_string_face(face: Face) -> &'static str {
     match face {
@@ -21,18 +22,19 @@ pub fn to_string_direction(direction: Direction) -> &'static str {
     }
 }
 
+/// `PuzzleTrait::encode_config` of whether moves also turn the opposite face.
+pub fn encode_opposite_move(with_opposite_move: bool) -> &'static str {
+    if with_opposite_move {
+        "opposite"
+    } else {
+        "plain"
+    }
+}
+
 pub fn get_all_moves() -> Vec<Move> {
-    let all_faces = [
-        Face::TopLeft,
-        Face::Left,
-        Face::BottomLeft,
-        Face::TopRight,
-        Face::Right,
-        Face::BottomRight,
-    ];
     let all_directions = [Direction::Clockwise, Direction::CounterClockwise];
     let mut all_moves = Vec::new();
-    for face in all_faces.iter() {
+    for face in Face::ALL.iter() {
         for direction in all_directions.iter() {
             all_moves.push(Move::new(*face, *direction));
         }
@@ -40,83 +42,53 @@ pub fn get_all_moves() -> Vec<Move> {
     all_moves
 }
 
+/// A scramble drawn with a fresh seed; use `ScrambleGenerator` to reproduce it.
 pub fn get_random_scramble(num_moves: usize) -> Scramble {
-    let all_faces = [
-        Face::TopLeft,
-        Face::Left,
-        Face::BottomLeft,
-        Face::TopRight,
-        Face::Right,
-        Face::BottomRight,
-    ];
-    let all_directions = [Direction::Clockwise, Direction::CounterClockwise];
-    let mut rng = thread_rng();
-    let mut scramble = Vec::new();
-    for _ in 0..num_moves {
-        let face = all_faces.choose(&mut rng).unwrap();
-        let direction = all_directions.choose(&mut rng).unwrap();
-        scramble.push(Move::new(*face, *direction));
-    }
-    Scramble { moves: scramble }
+    ScrambleGenerator::builtin(random_seed()).next_scramble(num_moves)
 }
 
-pub fn get_color(num: u8) -> u8 {
-    match num {
-        0 | 4 | 5 | 23 => 0,    // white
-        1 | 2 | 3 | 6 => 1,     // red
-        7 | 8 | 9 | 10 => 2,    // blue
-        11 | 12 | 13 | 14 => 3, // orange
-        15 | 16 | 17 | 18 => 4, // green
-        19 | 20 | 21 | 22 => 5, // yellow
-        _ => panic!("Invalid number"),
-    }
+/// Color of the piece that starts in slot `num` of the builtin puzzle.
+pub fn get_color(num: u8) -> Result<u8, SolverError> {
+    builtin_spec()
+        .colors
+        .get(num as usize)
+        .copied()
+        .ok_or_else(|| SolverError::InvalidState(format!("no color for slot {}", num)))
 }
 
-pub fn calculate_neighbours(num: u8) -> Vec<u8> {
-    match num {
-        0 => vec![1, 5],
-        1 => vec![0, 2],
-        2 => vec![1, 3, 6],
-        3 => vec![2, 4],
-        4 => vec![3, 5, 21],
-        5 => vec![0, 4, 23],
-        6 => vec![2, 7],
-        7 => vec![6, 8],
-        8 => vec![7, 9, 10],
-        9 => vec![3, 8, 13],
-        10 => vec![8, 11],
-        11 => vec![10, 12],
-        12 => vec![11, 13, 14],
-        13 => vec![9, 12, 17],
-        14 => vec![12, 15],
-        15 => vec![14, 16],
-        16 => vec![15, 17, 18],
-        17 => vec![13, 16, 21],
-        18 => vec![17, 19],
-        19 => vec![18, 21],
-        20 => vec![19, 21, 22],
-        21 => vec![4, 17, 20],
-        22 => vec![21, 23],
-        23 => vec![5, 22],
-        _ => panic!("Invalid number"),
-    }
+/// Slots next to slot `num` of the builtin puzzle.
+pub fn calculate_neighbours(num: u8) -> Result<&'static [u8], SolverError> {
+    builtin_spec()
+        .neighbours
+        .get(num as usize)
+        .map(Vec::as_slice)
+        .ok_or_else(|| SolverError::InvalidState(format!("no neighbours for slot {}", num)))
 }
 
-pub fn permutations(input: Vec<u8>) -> Vec<Vec<u8>> {
-    fn helper(prefix: Vec<u8>, remainder: Vec<u8>, acc: &mut Vec<Vec<u8>>) {
-        if remainder.is_empty() {
-            acc.push(prefix);
-        } else {
-            for i in 0..remainder.len() {
-                let mut next_prefix = prefix.clone();
-                next_prefix.push(remainder[i]);
-                let mut next_remainder = remainder.clone();
-                next_remainder.remove(i);
-                helper(next_prefix, next_remainder, acc);
+/// Number of directed neighbour pairs that show the same color, for the
+/// neighbours of every slot.
+pub fn score_neighbours(colors: &[u8], neighbours: &[Vec<u8>]) -> i64 {
+    let mut score = 0;
+    for (&color, neighbours) in colors.iter().zip(neighbours) {
+        for &neighbour in neighbours {
+            if colors[neighbour as usize] == color {
+                score += 1;
             }
         }
     }
-    let mut acc = Vec::new();
-    helper(Vec::new(), input, &mut acc);
-    acc
+    score
+}
+
+/// Reads one part of a stored record; running out of input here means the record was cut short.
+pub fn read_record_part(
+    reader: &mut impl std::io::Read,
+    buf: &mut [u8],
+) -> Result<(), SolverError> {
+    reader.read_exact(buf).map_err(|err| {
+        if err.kind() == std::io::ErrorKind::UnexpectedEof {
+            SolverError::CorruptBatch("truncated record".to_string())
+        } else {
+            SolverError::Io(err)
+        }
+    })
 }
@@ -0,0 +1,37 @@
+pub mod canonical;
+pub mod color_puzzle;
+pub mod error;
+pub mod external_sort;
+pub mod helpers;
+pub mod notation;
+pub mod packed;
+pub mod permutation;
+pub mod puzzle_spec;
+pub mod puzzle_trait;
+pub mod reachable;
+pub mod record;
+pub mod scramble;
+pub mod scramble_generator;
+pub mod single_puzzle;
+pub mod solver;
+pub mod spec_puzzle;
+pub mod stabilizer_chain;
+pub mod table;
+pub mod validate;
+
+pub use canonical::MoveRules;
+pub use color_puzzle::ColorPuzzle;
+pub use error::SolverError;
+pub use notation::StateNotation;
+pub use packed::PackedState;
+pub use permutation::Permutation;
+pub use puzzle_spec::{FaceSpec, PuzzleSpec};
+pub use puzzle_trait::PuzzleTrait;
+pub use reachable::{Batch, OverlapPairs, ReachableStates};
+pub use record::Record;
+pub use scramble::Scramble;
+pub use scramble_generator::{ScrambleGenerator, ScrambleMode};
+pub use single_puzzle::{Direction, Face, Move, SinglePuzzle};
+pub use solver::{SolveOptions, SolveReport, Solver};
+pub use spec_puzzle::{SpecMove, SpecPuzzle, SpecPuzzleConfig};
+pub use validate::{ValidationIssue, ValidationReport};
@@ -1,107 +1,876 @@
-mod single_puzzle;
-mod scramble;
-mod reachable;
-mod helpers;
-mod puzzle_trait;
-
-use clap::Parser;
-use single_puzzle::SinglePuzzle;
-use scramble::Scramble;
-use reachable::ReachableStates;
-use helpers::get_random_scramble;
+use clap::{Parser, Subcommand, ValueEnum};
+use cube_solver::external_sort::RunReader;
+use cube_solver::notation::COLOR_LETTERS;
+use cube_solver::scramble_generator::random_seed;
+use cube_solver::{
+    ColorPuzzle, Move, PuzzleSpec, PuzzleTrait, ReachableStates, Scramble, ScrambleGenerator,
+    ScrambleMode, SinglePuzzle, SolveOptions, Solver, SolverError, SpecPuzzle, SpecPuzzleConfig,
+    StateNotation,
+};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::sync::Arc;
+use std::time::Instant;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// Which puzzle a command works on.
+#[derive(clap::Args, Debug)]
+struct PuzzleArgs {
     /// Use opposite move logic
     #[arg(long, default_value_t = false)]
     with_opposite_move: bool,
+    /// Use a puzzle described by this spec file instead of the builtin one
+    #[arg(long, conflicts_with = "color_space")]
+    puzzle_spec: Option<String>,
+    /// Search colors only, treating pieces of the same color as interchangeable
+    #[arg(long, default_value_t = false)]
+    color_space: bool,
+}
+
+/// How states are generated and stored while searching.
+#[derive(clap::Args, Debug)]
+struct SearchArgs {
     /// Batch size
     #[arg(long, default_value_t = 1_000_000)]
     batch_size: usize,
-    /// Number of score weakens
-    #[arg(long, default_value_t = 3)]
-    num_score_weakens: usize,
-    /// Number of moves in scramble
-    #[arg(long, default_value_t = 50)]
-    scramble_moves: usize,
-    /// Starting depth
-    #[arg(long, default_value_t = 10)]
+    /// Number of sorted runs merged at once
+    #[arg(long, default_value_t = 16, value_parser = clap::value_parser!(u64).range(2..))]
+    merge_fan_in: u64,
+    /// Number of score weakens, at most 8
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u64).range(..=8))]
+    num_score_weakens: u64,
+    /// Search without score pruning and report the optimal solution length
+    #[arg(long, default_value_t = false)]
+    exact: bool,
+}
+
+/// The depths of a solve and the solved side it joins against.
+#[derive(clap::Args, Debug)]
+struct DepthArgs {
+    /// Moves searched from each side at first; the sides then grow one move
+    /// at a time
+    #[arg(long, default_value_t = 0)]
     start_depth: usize,
-    /// Maximum depth
+    /// Most moves searched from each side, unless limited separately below
     #[arg(long, default_value_t = 13)]
     max_depth: usize,
+    /// Most moves searched from the scrambled puzzle, --max-depth if not given
+    #[arg(long)]
+    max_forward_depth: Option<usize>,
+    /// Most moves searched from the solved puzzle, --max-depth if not given;
+    /// a --solved-table is joined at the depth it was built with
+    #[arg(long)]
+    max_backward_depth: Option<usize>,
+    /// Use the table built by build-table in this directory as the solved side
+    #[arg(long)]
+    solved_table: Option<String>,
+}
+
+/// A puzzle given on the command line: the state to start from, solved if
+/// not given, and a scramble applied to it.
+#[derive(clap::Args, Debug)]
+struct InputArgs {
+    /// Scramble to apply, e.g. "TL CW; R CCW;" or "TL R' BL3"
+    #[arg(long, conflicts_with = "scramble_file")]
+    scramble: Option<String>,
+    /// Read the scramble to apply from this file, line by line
+    #[arg(long)]
+    scramble_file: Option<String>,
+    /// State to start from, given as 24 slot ids or 24 color letters (W R B O G Y)
+    #[arg(long)]
+    state: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Solve a scramble or state, a random scramble if none is given
+    Solve {
+        #[command(flatten)]
+        puzzle: PuzzleArgs,
+        #[command(flatten)]
+        search: SearchArgs,
+        #[command(flatten)]
+        depths: DepthArgs,
+        #[command(flatten)]
+        input: InputArgs,
+        /// Number of moves in the random scramble
+        #[arg(long, default_value_t = 50)]
+        scramble_moves: usize,
+        /// Seed of the random scramble, a fresh one if not given
+        #[arg(long)]
+        seed: Option<u64>,
+        /// How the random scramble is drawn
+        #[arg(long, value_enum, default_value_t = ModeArg::Random)]
+        scramble_mode: ModeArg,
+    },
+    /// Solve every scramble of a file, one per line in `TL CW; R CCW;` notation
+    SolveBatch {
+        #[command(flatten)]
+        puzzle: PuzzleArgs,
+        #[command(flatten)]
+        search: SearchArgs,
+        #[command(flatten)]
+        depths: DepthArgs,
+        /// File to read the scrambles from, stdin if not given or `-`
+        input: Option<String>,
+        /// Depth of the solved-side table built for the batch when
+        /// --solved-table is not given
+        #[arg(long, default_value_t = 10)]
+        table_depth: usize,
+    },
+    /// Print random scrambles, one per line after a `# seed` line
+    Scramble {
+        #[command(flatten)]
+        puzzle: PuzzleArgs,
+        /// Number of moves in each scramble
+        #[arg(long, default_value_t = 50)]
+        moves: usize,
+        /// Number of scrambles
+        #[arg(long, default_value_t = 1)]
+        count: usize,
+        /// Seed of the scrambles, a fresh one if not given
+        #[arg(long)]
+        seed: Option<u64>,
+        /// Print the compact form, e.g. "TL R' BL3"
+        #[arg(long, default_value_t = false)]
+        compact: bool,
+        /// How the scrambles are drawn
+        #[arg(long, value_enum, default_value_t = ModeArg::Random)]
+        mode: ModeArg,
+    },
+    /// Apply a scramble and print the resulting state
+    Apply {
+        #[command(flatten)]
+        puzzle: PuzzleArgs,
+        #[command(flatten)]
+        input: InputArgs,
+    },
+    /// Show the pieces on every face of a state
+    Render {
+        #[command(flatten)]
+        puzzle: PuzzleArgs,
+        #[command(flatten)]
+        input: InputArgs,
+    },
+    /// Check the move tables, opposite moves and neighbour graph of a puzzle
+    #[command(alias = "verify-puzzle")]
+    Verify {
+        #[command(flatten)]
+        puzzle: PuzzleArgs,
+    },
+    /// Search from the solved puzzle once and keep the result as a table
+    BuildTable {
+        #[command(flatten)]
+        puzzle: PuzzleArgs,
+        #[command(flatten)]
+        search: SearchArgs,
+        /// Most moves from the solved puzzle
+        #[arg(long)]
+        depth: usize,
+        /// Directory to write the table to
+        #[arg(long, default_value = "solved_table")]
+        table: String,
+    },
+    /// Count the states within each number of moves of the solved puzzle
+    Stats {
+        #[command(flatten)]
+        puzzle: PuzzleArgs,
+        #[command(flatten)]
+        search: SearchArgs,
+        /// Search this many moves from the solved puzzle
+        #[arg(long, required_unless_present = "table", conflicts_with = "table")]
+        depth: Option<usize>,
+        /// Read the counts from a table built by build-table instead
+        #[arg(long)]
+        table: Option<String>,
+    },
+    /// Print the states stored in a batch file
+    InspectBatch {
+        #[command(flatten)]
+        puzzle: PuzzleArgs,
+        /// Batch file, e.g. layer_3/states_0.bin of a table
+        path: String,
+        /// Most states to print
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+    },
+}
+
+/// `PuzzleArgs` resolved to one of the puzzle implementations.
+enum PuzzleKind {
+    Spec(SpecPuzzleConfig),
+    Colors(bool),
+    Slots(bool),
+}
+
+impl PuzzleArgs {
+    fn kind(&self) -> PuzzleKind {
+        match &self.puzzle_spec {
+            Some(path) => PuzzleKind::Spec(SpecPuzzleConfig {
+                spec: load_spec(path),
+                with_opposite_move: self.with_opposite_move,
+            }),
+            None if self.color_space => PuzzleKind::Colors(self.with_opposite_move),
+            None => PuzzleKind::Slots(self.with_opposite_move),
+        }
+    }
+
+    /// `kind`, exiting with the validation report if the given spec does not
+    /// describe a valid puzzle. Searching an invalid one gives meaningless results.
+    fn validated_kind(&self) -> PuzzleKind {
+        let kind = self.kind();
+        if let PuzzleKind::Spec(config) = &kind {
+            let report = config.spec.validate();
+            if !report.is_ok() {
+                eprint!("{}", report);
+                std::process::exit(1);
+            }
+        }
+        kind
+    }
+
+    /// The builtin puzzle's config, exiting for commands that only know its notation.
+    fn builtin(&self, command: &str) -> bool {
+        if self.puzzle_spec.is_some() {
+            eprintln!("Error: {} only supports the builtin puzzle", command);
+            std::process::exit(1);
+        }
+        self.with_opposite_move
+    }
+}
+
+impl SearchArgs {
+    fn options(&self) -> SolveOptions {
+        SolveOptions {
+            batch_size: self.batch_size,
+            merge_fan_in: self.merge_fan_in as usize,
+            num_score_weakens: self.num_score_weakens as usize,
+            exact: self.exact,
+            ..SolveOptions::default()
+        }
+    }
+
+    fn solve_options(&self, depths: &DepthArgs) -> SolveOptions {
+        SolveOptions {
+            start_depth: depths.start_depth,
+            max_forward_depth: depths.max_forward_depth.unwrap_or(depths.max_depth),
+            max_backward_depth: depths.max_backward_depth.unwrap_or(depths.max_depth),
+            solved_table: depths.solved_table.clone(),
+            ..self.options()
+        }
+    }
+}
+
+impl InputArgs {
+    fn is_given(&self) -> bool {
+        self.scramble.is_some() || self.scramble_file.is_some() || self.state.is_some()
+    }
+
+    fn state(&self) -> Result<Option<StateNotation>, String> {
+        self.state
+            .as_deref()
+            .map(|text| text.parse().map_err(|err| format!("--state: {}", err)))
+            .transpose()
+    }
+
+    fn scramble(&self) -> Result<Scramble, String> {
+        if let Some(text) = &self.scramble {
+            return text.parse().map_err(|err| format!("--scramble: {}", err));
+        }
+        let mut moves = Vec::new();
+        if let Some(path) = &self.scramble_file {
+            let text = std::fs::read_to_string(path)
+                .map_err(|err| format!("can not read {}: {}", path, err))?;
+            for (number, line) in text.lines().enumerate() {
+                if line.trim_start().starts_with('#') {
+                    continue;
+                }
+                let scramble: Scramble = line
+                    .parse()
+                    .map_err(|err| format!("{}: line {}: {}", path, number + 1, err))?;
+                moves.extend(scramble.moves);
+            }
+        }
+        Ok(Scramble { moves })
+    }
+
+    /// The state the scramble is applied to.
+    fn single_start(&self) -> SinglePuzzle {
+        exit_on_error(match exit_on_error(self.state()) {
+            None => Ok(SinglePuzzle::new_solved()),
+            Some(StateNotation::Slots(slots)) => SinglePuzzle::from_slots(slots.to_vec()),
+            Some(StateNotation::Colors(colors)) => SinglePuzzle::from_colors(colors),
+        })
+    }
+
+    fn single_puzzle(&self, with_opposite_move: bool) -> SinglePuzzle {
+        let mut puzzle = self.single_start();
+        puzzle.apply_scramble(exit_on_error(self.scramble()), &with_opposite_move);
+        puzzle
+    }
+
+    /// The state the scramble is applied to.
+    fn color_start(&self) -> ColorPuzzle {
+        exit_on_error(match exit_on_error(self.state()) {
+            None => Ok(ColorPuzzle::new_solved()),
+            Some(StateNotation::Slots(slots)) => ColorPuzzle::from_slots(slots),
+            Some(StateNotation::Colors(colors)) => ColorPuzzle::from_colors(colors),
+        })
+    }
+
+    fn color_puzzle(&self, with_opposite_move: bool) -> ColorPuzzle {
+        let mut puzzle = self.color_start();
+        puzzle.apply_scramble(exit_on_error(self.scramble()), &with_opposite_move);
+        puzzle
+    }
+}
+
+/// Command line names of the `ScrambleMode`s.
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ModeArg {
+    /// Independent random moves
+    Random,
+    /// Random moves without cancellations, overlong runs of a face or
+    /// commuting faces out of order
+    Canonical,
+    /// A uniformly random reachable state; --moves is ignored
+    RandomState,
+}
+
+impl From<ModeArg> for ScrambleMode {
+    fn from(mode: ModeArg) -> Self {
+        match mode {
+            ModeArg::Random => ScrambleMode::Random,
+            ModeArg::Canonical => ScrambleMode::Canonical,
+            ModeArg::RandomState => ScrambleMode::RandomState,
+        }
+    }
 }
 
 fn main() {
-    let args = Args::parse();
-    let scramble = get_random_scramble(args.scramble_moves);
-    for i in args.start_depth..args.max_depth {
-        let found_solution = find_solution(
-            i,
-            scramble.clone(),
-            args.with_opposite_move,
-            args.batch_size,
-            args.num_score_weakens,
-        );
-        if found_solution {
-            break;
+    match Args::parse().command {
+        Command::Solve {
+            puzzle,
+            search,
+            depths,
+            input,
+            scramble_moves,
+            seed,
+            scramble_mode,
+        } => {
+            let options = search.solve_options(&depths);
+            let seed = seed.unwrap_or_else(random_seed);
+            if !input.is_given() {
+                println!("Seed: {}", seed);
+            }
+            match puzzle.validated_kind() {
+                PuzzleKind::Spec(_) if input.is_given() => {
+                    eprintln!(
+                        "Error: --scramble, --scramble-file and --state only support the builtin puzzle"
+                    );
+                    std::process::exit(1);
+                }
+                PuzzleKind::Spec(config) => {
+                    let solved = SpecPuzzle::new_solved(&config.spec);
+                    let scramble = exit_on_error(ScrambleGenerator::for_puzzle::<SpecPuzzle>(
+                        seed,
+                        &config,
+                        scramble_mode.into(),
+                    ))
+                    .next_scramble(scramble_moves);
+                    solve(&solved, &scramble, &config, options, |mv| {
+                        config.format_move(mv)
+                    });
+                }
+                PuzzleKind::Colors(with_opposite_move) => {
+                    let (start, scramble) = if input.is_given() {
+                        (input.color_start(), exit_on_error(input.scramble()))
+                    } else {
+                        (
+                            ColorPuzzle::new_solved(),
+                            builtin_generator(seed, with_opposite_move, scramble_mode)
+                                .next_scramble(scramble_moves),
+                        )
+                    };
+                    solve(&start, &scramble, &with_opposite_move, options, |mv| {
+                        mv.to_string()
+                    });
+                }
+                PuzzleKind::Slots(with_opposite_move) => {
+                    let (start, scramble) = if input.is_given() {
+                        (input.single_start(), exit_on_error(input.scramble()))
+                    } else {
+                        (
+                            SinglePuzzle::new_solved(),
+                            builtin_generator(seed, with_opposite_move, scramble_mode)
+                                .next_scramble(scramble_moves),
+                        )
+                    };
+                    solve(&start, &scramble, &with_opposite_move, options, |mv| {
+                        mv.to_string()
+                    });
+                }
+            }
+        }
+        Command::SolveBatch {
+            puzzle,
+            search,
+            depths,
+            input,
+            table_depth,
+        } => {
+            let with_opposite_move = puzzle.builtin("solve-batch");
+            let options = search.solve_options(&depths);
+            let input = input.as_deref().filter(|&path| path != "-");
+            if puzzle.color_space {
+                solve_batch::<ColorPuzzle>(input, table_depth, options, &with_opposite_move);
+            } else {
+                solve_batch::<SinglePuzzle>(input, table_depth, options, &with_opposite_move);
+            }
+        }
+        Command::Scramble {
+            puzzle,
+            moves,
+            count,
+            seed,
+            compact,
+            mode,
+        } => {
+            let seed = seed.unwrap_or_else(random_seed);
+            println!("# seed {}", seed);
+            match puzzle.kind() {
+                PuzzleKind::Spec(config) => {
+                    let mut generator = exit_on_error(ScrambleGenerator::for_puzzle::<SpecPuzzle>(
+                        seed,
+                        &config,
+                        mode.into(),
+                    ));
+                    for scramble in generator.scrambles(count, moves) {
+                        let moves: Vec<String> = scramble
+                            .moves
+                            .iter()
+                            .map(|&mv| config.format_move(mv))
+                            .collect();
+                        println!("{}", moves.join(" "));
+                    }
+                }
+                PuzzleKind::Colors(with_opposite_move) | PuzzleKind::Slots(with_opposite_move) => {
+                    let mut generator = builtin_generator(seed, with_opposite_move, mode);
+                    for scramble in generator.scrambles(count, moves) {
+                        if compact {
+                            println!("{:#}", scramble);
+                        } else {
+                            println!("{}", scramble);
+                        }
+                    }
+                }
+            }
+        }
+        Command::Apply { puzzle, input } => {
+            let with_opposite_move = puzzle.builtin("apply");
+            if puzzle.color_space {
+                let puzzle = input.color_puzzle(with_opposite_move);
+                println!("colors: {}", StateNotation::Colors(puzzle.colors()));
+            } else {
+                let puzzle = input.single_puzzle(with_opposite_move);
+                println!("slots: {}", StateNotation::Slots(puzzle.slots()));
+                println!("colors: {}", StateNotation::Colors(puzzle.colors()));
+            }
+        }
+        Command::Render { puzzle, input } => {
+            let with_opposite_move = puzzle.builtin("render");
+            if puzzle.color_space {
+                render(None, &input.color_puzzle(with_opposite_move).colors());
+            } else {
+                let puzzle = input.single_puzzle(with_opposite_move);
+                render(Some(&puzzle.slots()), &puzzle.colors());
+            }
+        }
+        Command::Verify { puzzle } => verify_puzzle(puzzle.puzzle_spec.as_deref()),
+        Command::BuildTable {
+            puzzle,
+            search,
+            depth,
+            table,
+        } => {
+            let options = search.options();
+            match puzzle.validated_kind() {
+                PuzzleKind::Spec(config) => {
+                    build_table::<SpecPuzzle>(&config, depth, &table, &options)
+                }
+                PuzzleKind::Colors(config) => {
+                    build_table::<ColorPuzzle>(&config, depth, &table, &options)
+                }
+                PuzzleKind::Slots(config) => {
+                    build_table::<SinglePuzzle>(&config, depth, &table, &options)
+                }
+            }
+        }
+        Command::Stats {
+            puzzle,
+            search,
+            depth,
+            table,
+        } => {
+            let options = search.options();
+            let result = match puzzle.validated_kind() {
+                PuzzleKind::Spec(config) => {
+                    stats::<SpecPuzzle>(&config, depth, table.as_deref(), &options)
+                }
+                PuzzleKind::Colors(config) => {
+                    stats::<ColorPuzzle>(&config, depth, table.as_deref(), &options)
+                }
+                PuzzleKind::Slots(config) => {
+                    stats::<SinglePuzzle>(&config, depth, table.as_deref(), &options)
+                }
+            };
+            exit_on_error(result);
+        }
+        Command::InspectBatch {
+            puzzle,
+            path,
+            limit,
+        } => {
+            let result = match puzzle.kind() {
+                PuzzleKind::Spec(config) => {
+                    inspect_batch::<SpecPuzzle>(&config, &path, limit, |state, scramble| {
+                        let moves: Vec<String> = scramble
+                            .moves
+                            .iter()
+                            .map(|&mv| config.format_move(mv))
+                            .collect();
+                        let colors: Vec<String> = state.colors.iter().map(u8::to_string).collect();
+                        format!("{}\t{}", colors.join(" "), moves.join(" "))
+                    })
+                }
+                PuzzleKind::Colors(config) => {
+                    inspect_batch::<ColorPuzzle>(&config, &path, limit, |state, scramble| {
+                        format!("{}\t{}", StateNotation::Colors(state.colors()), scramble)
+                    })
+                }
+                PuzzleKind::Slots(config) => {
+                    inspect_batch::<SinglePuzzle>(&config, &path, limit, |state, scramble| {
+                        format!("{}\t{}", StateNotation::Colors(state.colors()), scramble)
+                    })
+                }
+            };
+            exit_on_error(result);
         }
     }
 }
 
-fn find_solution(
+/// Scrambles of the builtin puzzle's moves; the slot and color puzzles share
+/// them, and a uniform slot state is also a uniform color state.
+fn builtin_generator(seed: u64, with_opposite_move: bool, mode: ModeArg) -> ScrambleGenerator {
+    exit_on_error(ScrambleGenerator::for_puzzle::<SinglePuzzle>(
+        seed,
+        &with_opposite_move,
+        mode.into(),
+    ))
+}
+
+fn exit_on_error<T, E: std::fmt::Display>(result: Result<T, E>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn load_spec(path: &str) -> Arc<PuzzleSpec> {
+    match PuzzleSpec::from_file(path) {
+        Ok(spec) => Arc::new(spec),
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn build_table<P: PuzzleTrait>(
+    config: &P::Config,
     depth: usize,
-    scramble: Scramble,
-    with_opposite_move: bool,
-    batch_size: usize,
-    num_score_weakens: usize,
-) -> bool {
-    let store_directory = "reachable_batches".to_string();
-    println!("Depth: {}, Scramble: {:?}", depth, scramble);
-    let scrambled_puzzle = SinglePuzzle::new_scrambled(scramble.clone(), with_opposite_move);
-    let reachable_states = ReachableStates::new(
-        depth,
-        scrambled_puzzle,
-        batch_size,
-        store_directory.clone(),
-        with_opposite_move,
-        num_score_weakens,
-        true,
-    );
-    reachable_states.print_first_5(with_opposite_move);
-    let all_solved_states = SinglePuzzle::get_solved_states(with_opposite_move);
-    for (i, solved_state) in all_solved_states.iter().enumerate() {
-        println!("Checking solved state {}...", i);
-        let solved_store_directory = format!("{}_solved_{}", store_directory, i);
-        let reachable_from_solved = ReachableStates::new(
-            depth,
-            solved_state.clone(),
-            batch_size,
-            solved_store_directory.clone(),
-            with_opposite_move,
+    table: &str,
+    options: &SolveOptions,
+) {
+    match Solver::build_table::<P>(config, depth, table, options) {
+        Ok(table) => println!(
+            "Wrote a table of depth {} with {} states to {}",
+            table.depth(),
+            table.layer_sizes.iter().sum::<usize>(),
+            table.store_directory
+        ),
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Prints the number of states first reached with each number of moves,
+/// either searched now up to `depth` or read from `table`.
+fn stats<P: PuzzleTrait>(
+    config: &P::Config,
+    depth: Option<usize>,
+    table: Option<&str>,
+    options: &SolveOptions,
+) -> Result<(), SolverError> {
+    let num_score_weakens = (!options.exact).then_some(options.num_score_weakens);
+    let states = match table {
+        Some(table) => ReachableStates::open_table(
+            table,
+            P::solved(config),
+            config.clone(),
             num_score_weakens,
             false,
-        );
-        let solve = reachable_from_solved.overlaps(&reachable_states, with_opposite_move);
-        match solve {
-            Some(solution) => {
-                println!("Found a solution with {} moves:", solution.moves.len());
-                for mv in solution.moves {
-                    print!("{}", mv.to_string());
+        )?,
+        None => {
+            let result = ReachableStates::new(
+                depth.unwrap_or(0),
+                P::solved(config),
+                config.clone(),
+                options.batch_size,
+                options.store_directory.clone(),
+                num_score_weakens,
+                false,
+                options.merge_fan_in,
+            );
+            std::fs::remove_dir_all(&options.store_directory).ok();
+            result?
+        }
+    };
+    let mut total = 0;
+    for (layer, &size) in states.layer_sizes.iter().enumerate() {
+        total += size;
+        match layer
+            .checked_sub(1)
+            .map(|previous| states.layer_sizes[previous])
+        {
+            Some(previous) if previous > 0 => println!(
+                "layer {}: {} states ({:.2}x the previous layer)",
+                layer,
+                size,
+                size as f64 / previous as f64
+            ),
+            _ => println!("layer {}: {} states", layer, size),
+        }
+    }
+    println!("total: {} states within {} moves", total, states.depth());
+    if table.is_none() {
+        println!("nodes expanded: {}", states.nodes_expanded);
+    }
+    Ok(())
+}
+
+/// Prints up to `limit` states of a batch file, each described by
+/// `describe` from its state and moves, and checks that the file is sorted
+/// without duplicates.
+fn inspect_batch<P: PuzzleTrait>(
+    config: &P::Config,
+    path: &str,
+    limit: usize,
+    describe: impl Fn(&P, &Scramble<P::Move>) -> String,
+) -> Result<(), SolverError> {
+    let mut reader = RunReader::<P>::open(path, config)?;
+    let mut count = 0;
+    let mut unsorted = 0;
+    let mut duplicates = 0;
+    let mut longest = 0;
+    let mut previous: Option<P> = None;
+    while let Some(record) = reader.next_record()? {
+        if count < limit {
+            println!(
+                "{}\t{}",
+                count,
+                describe(&record.state, &record.scramble(config)?)
+            );
+        }
+        if let Some(previous) = &previous {
+            match previous.cmp(&record.state) {
+                std::cmp::Ordering::Less => {}
+                std::cmp::Ordering::Equal => duplicates += 1,
+                std::cmp::Ordering::Greater => unsorted += 1,
+            }
+        }
+        longest = longest.max(record.moves.len());
+        count += 1;
+        previous = Some(record.state);
+    }
+    println!(
+        "{} states, longest scramble {} moves, {} out of order, {} duplicates",
+        count, longest, unsorted, duplicates
+    );
+    Ok(())
+}
+
+/// Prints the pieces of every face of the builtin puzzle in the order of
+/// its clockwise cycle, with slot ids if known.
+fn render(slots: Option<&[u8; 24]>, colors: &[u8; 24]) {
+    for face in PuzzleSpec::builtin().faces {
+        let pieces: Vec<String> = face
+            .cycle
+            .iter()
+            .map(|&slot| {
+                let color = COLOR_LETTERS[colors[slot as usize] as usize];
+                match slots {
+                    Some(slots) => format!("{:>2}:{}", slots[slot as usize], color),
+                    None => color.to_string(),
                 }
-                println!();
-                std::fs::remove_dir_all(&store_directory).ok();
-                std::fs::remove_dir_all(&solved_store_directory).ok();
-                return true;
+            })
+            .collect();
+        println!("{:<2} {}", face.name, pieces.join(" "));
+    }
+}
+
+/// Solves the scrambles of `input`, applied to the solved puzzle of `config`,
+/// against one shared solved-side table and writes a tab separated line per
+/// scramble: the scramble, the solution, its length, the time taken in
+/// seconds and the number of nodes expanded. Without a given table one is
+/// built for the first valid scramble.
+fn solve_batch<P: PuzzleTrait<Move = Move>>(
+    input: Option<&str>,
+    table_depth: usize,
+    mut options: SolveOptions,
+    config: &P::Config,
+) {
+    let reader: Box<dyn BufRead> = match input {
+        Some(path) => match File::open(path) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(err) => {
+                eprintln!("Error: can not open {}: {}", path, err);
+                std::process::exit(1);
+            }
+        },
+        None => Box::new(BufReader::new(std::io::stdin())),
+    };
+    let mut built_table = None;
+    options.quiet = true;
+    println!("# scramble\tsolution\tlength\ttime_s\tnodes");
+    for line in reader.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                break;
+            }
+        };
+        let text = line.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+        let scramble = match text.parse::<Scramble>() {
+            Ok(scramble) => scramble,
+            Err(err) => {
+                print_batch_error(text, &err);
+                continue;
+            }
+        };
+        if options.solved_table.is_none() {
+            let table = format!("{}_table", options.store_directory);
+            if let Err(err) = Solver::build_table::<P>(config, table_depth, &table, &options) {
+                eprintln!("Error: {}", err);
+                std::process::exit(1);
             }
-            None => {
-                println!("No solution found for this solved state.");
+            options.solved_table = Some(table.clone());
+            built_table = Some(table);
+        }
+        let start = Instant::now();
+        let result = Solver::solve_scramble(&P::solved(config), &scramble, config, options.clone());
+        let elapsed = start.elapsed().as_secs_f64();
+        match result {
+            Ok(report) => match report.solution {
+                Some(solution) => println!(
+                    "{}\t{}\t{}\t{:.3}\t{}",
+                    text,
+                    solution,
+                    solution.moves.len(),
+                    elapsed,
+                    report.nodes_expanded
+                ),
+                None => println!("{}\t-\t-\t{:.3}\t{}", text, elapsed, report.nodes_expanded),
+            },
+            Err(err) => print_batch_error(text, &err),
+        }
+    }
+    if let Some(table) = &built_table {
+        std::fs::remove_dir_all(table).ok();
+    }
+}
+
+/// Keeps the output one row of five columns per scramble: only the first
+/// line of the error goes in the solution column, the other columns hold
+/// `-`, and the full message goes to stderr.
+fn print_batch_error(text: &str, err: &dyn std::fmt::Display) {
+    let message = err.to_string();
+    let summary = message.lines().next().unwrap_or("").replace('\t', " ");
+    println!("{}\terror: {}\t-\t-\t-", text, summary);
+    if message.contains('\n') {
+        eprintln!("Error for {}: {}", text, message);
+    }
+}
+
+fn solve<P: PuzzleTrait>(
+    start: &P,
+    scramble: &Scramble<P::Move>,
+    config: &P::Config,
+    options: SolveOptions,
+    format_move: impl Fn(P::Move) -> String,
+) {
+    let report = match Solver::solve_scramble(start, scramble, config, options) {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+    };
+    for ((forward, backward), lower_bound) in
+        report.depths_searched.iter().zip(&report.lower_bounds)
+    {
+        println!(
+            "No solution at depths {} forward, {} backward: every solution has at least {} moves",
+            forward, backward, lower_bound
+        );
+    }
+    match report.solution {
+        Some(solution) => {
+            let kind = if report.optimal {
+                "an optimal solution"
+            } else {
+                "a solution"
+            };
+            println!("Found {} with {} moves:", kind, solution.moves.len());
+            for mv in solution.moves {
+                print!("{}", format_move(mv));
             }
+            println!();
+        }
+        None => {
+            println!("No solution found.");
         }
-        std::fs::remove_dir_all(&solved_store_directory).ok();
-        break;
     }
-    std::fs::remove_dir_all(&store_directory).ok();
-    false
-}
\ No newline at end of file
+}
+
+fn verify_puzzle(puzzle_spec: Option<&str>) {
+    let report = match puzzle_spec {
+        Some(path) => match PuzzleSpec::from_file(path) {
+            Ok(spec) => spec.validate(),
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                std::process::exit(1);
+            }
+        },
+        None => SinglePuzzle::validate(),
+    };
+    print!("{}", report);
+    if !report.is_ok() {
+        std::process::exit(1);
+    }
+}
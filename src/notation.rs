@@ -0,0 +1,356 @@
+//! Text form of faces, directions, moves and scrambles.
+//!
+//! The verbose form is what `Display` prints, a face and a direction per move:
+//! `TL CW; R CCW;`. The compact form writes a clockwise move as its face and a
+//! counter clockwise one with a trailing `'`, with an optional count of turns
+//! in between: `TL R' BL3 TR2'`. A count is at most 5, one less than the
+//! order of a face. The alternate flag (`{:#}`) prints the compact form. When
+//! parsing, both forms may be mixed and moves are separated by whitespace or
+//! `;`. Errors carry the column of the offending word.
+//!
+//! States are written as slot ids or color letters, see `StateNotation`.
+
+use crate::error::SolverError;
+use crate::helpers::{to_string_direction, to_string_face};
+use crate::scramble::Scramble;
+use crate::single_puzzle::{Direction, Face, Move};
+use std::fmt;
+use std::str::FromStr;
+
+const DIRECTIONS: [Direction; 2] = [Direction::Clockwise, Direction::CounterClockwise];
+
+impl fmt::Display for Face {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(to_string_face(*self))
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(to_string_direction(*self))
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            write_compact(f, *self, 1)
+        } else {
+            write!(f, "{} {};", self.face, self.direction)
+        }
+    }
+}
+
+impl fmt::Display for Scramble {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = true;
+        let mut i = 0;
+        while i < self.moves.len() {
+            let mv = self.moves[i];
+            // runs as long as the face's order would not parse back, so they
+            // are split
+            let run = if f.alternate() {
+                self.moves[i..]
+                    .iter()
+                    .take(face_order(mv.face) - 1)
+                    .take_while(|&&next| next == mv)
+                    .count()
+            } else {
+                1
+            };
+            if !first {
+                f.write_str(" ")?;
+            }
+            first = false;
+            if f.alternate() {
+                write_compact(f, mv, run)?;
+            } else {
+                write!(f, "{}", mv)?;
+            }
+            i += run;
+        }
+        Ok(())
+    }
+}
+
+fn write_compact(f: &mut fmt::Formatter<'_>, mv: Move, count: usize) -> fmt::Result {
+    write!(f, "{}", mv.face)?;
+    if count > 1 {
+        write!(f, "{}", count)?;
+    }
+    if mv.direction == Direction::CounterClockwise {
+        f.write_str("'")?;
+    }
+    Ok(())
+}
+
+impl FromStr for Face {
+    type Err = SolverError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        parse_face(text).ok_or_else(|| invalid(text, 0, format!("unknown face {}", text)))
+    }
+}
+
+impl FromStr for Direction {
+    type Err = SolverError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        parse_direction(text).ok_or_else(|| invalid(text, 0, format!("unknown direction {}", text)))
+    }
+}
+
+impl FromStr for Move {
+    type Err = SolverError;
+
+    /// Accepts a single move in either form, e.g. `TL CW;`, `TL` or `TL'`.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let scramble: Scramble = text.parse()?;
+        match scramble.moves[..] {
+            [mv] => Ok(mv),
+            _ => Err(invalid(
+                text,
+                0,
+                format!("expected one move, found {}", scramble.moves.len()),
+            )),
+        }
+    }
+}
+
+impl FromStr for Scramble {
+    type Err = SolverError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let words = words(text);
+        let mut moves = Vec::new();
+        let mut i = 0;
+        while i < words.len() {
+            let (offset, word) = words[i];
+            // a face followed by a direction in the same move is the verbose form
+            let verbose = words
+                .get(i + 1)
+                .filter(|&&(next_offset, _)| !text[offset..next_offset].contains(';'))
+                .and_then(|&(_, next)| parse_face(word).zip(parse_direction(next)));
+            if let Some((face, direction)) = verbose {
+                moves.push(Move::new(face, direction));
+                i += 2;
+                continue;
+            }
+            let (mv, count) = parse_compact(text, offset, word)?;
+            moves.extend(std::iter::repeat_n(mv, count));
+            i += 1;
+        }
+        Ok(Scramble { moves })
+    }
+}
+
+fn parse_face(word: &str) -> Option<Face> {
+    Face::ALL
+        .into_iter()
+        .find(|&face| to_string_face(face) == word)
+}
+
+fn parse_direction(word: &str) -> Option<Direction> {
+    DIRECTIONS
+        .into_iter()
+        .find(|&direction| to_string_direction(direction) == word)
+}
+
+/// Parses a compact move such as `TL`, `TL'` or `TL3'` starting at byte
+/// `offset` of `text`, returning the move and how often it is turned. The
+/// count has to be less than the face's order.
+fn parse_compact(text: &str, offset: usize, word: &str) -> Result<(Move, usize), SolverError> {
+    let (body, direction) = match word.strip_suffix('\'') {
+        Some(body) => (body, Direction::CounterClockwise),
+        None => (word, Direction::Clockwise),
+    };
+    let digits = body
+        .find(|c: char| c.is_ascii_digit())
+        .unwrap_or(body.len());
+    let (name, count) = body.split_at(digits);
+    let face =
+        parse_face(name).ok_or_else(|| invalid(text, offset, format!("unknown face {}", word)))?;
+    let count = match count {
+        "" => 1,
+        count => match count.parse() {
+            Ok(0) => {
+                return Err(invalid(
+                    text,
+                    offset + digits,
+                    "a move has to be turned at least once".to_string(),
+                ))
+            }
+            Ok(count) if count < face_order(face) => count,
+            Ok(_) => {
+                return Err(invalid(
+                    text,
+                    offset + digits,
+                    format!(
+                        "a move is turned at most {} times, found {}",
+                        face_order(face) - 1,
+                        count
+                    ),
+                ))
+            }
+            Err(_) => {
+                return Err(invalid(
+                    text,
+                    offset + digits,
+                    format!("invalid count {}", count),
+                ))
+            }
+        },
+    };
+    Ok((Move::new(face, direction), count))
+}
+
+/// How often a face has to be turned to get back to where it started.
+fn face_order(face: Face) -> usize {
+    face.cycle().len()
+}
+
+/// The words of `text` with their byte offsets; `;` separates words like
+/// whitespace does.
+fn words(text: &str) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        if c.is_whitespace() || c == ';' {
+            if let Some(start) = start.take() {
+                words.push((start, &text[start..i]));
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(start) = start {
+        words.push((start, &text[start..]));
+    }
+    words
+}
+
+fn invalid(text: &str, offset: usize, reason: String) -> SolverError {
+    SolverError::InvalidNotation {
+        column: text[..offset].chars().count() + 1,
+        reason,
+    }
+}
+
+/// Letters of the colors as numbered by `helpers::get_color`: white, red,
+/// blue, orange, green and yellow.
+pub const COLOR_LETTERS: [char; 6] = ['W', 'R', 'B', 'O', 'G', 'Y'];
+
+/// A puzzle state in text form, slot 0 first: either 24 slot ids separated by
+/// whitespace or commas, e.g. `0 1 2 ... 23`, or 24 color letters from
+/// `COLOR_LETTERS` with optional whitespace, e.g. `WRRRWW...`. Parsing only
+/// checks the syntax; the puzzle constructors check that the ids form a
+/// permutation and that every color appears four times.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateNotation {
+    Slots([u8; 24]),
+    Colors([u8; 24]),
+}
+
+impl fmt::Display for StateNotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateNotation::Slots(slots) => {
+                let ids: Vec<String> = slots.iter().map(u8::to_string).collect();
+                f.write_str(&ids.join(" "))
+            }
+            StateNotation::Colors(colors) => colors
+                .iter()
+                .try_for_each(|&color| write!(f, "{}", COLOR_LETTERS[color as usize])),
+        }
+    }
+}
+
+impl FromStr for StateNotation {
+    type Err = SolverError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        if text.contains(|c: char| c.is_ascii_digit()) {
+            let mut slots = Vec::with_capacity(24);
+            let mut start = None;
+            for (i, c) in text.char_indices().chain([(text.len(), ' ')]) {
+                if c.is_whitespace() || c == ',' {
+                    if let Some(start) = start.take() {
+                        let id = text[start..i].parse().map_err(|_| {
+                            invalid(text, start, format!("invalid slot id {}", &text[start..i]))
+                        })?;
+                        slots.push(id);
+                    }
+                } else if start.is_none() {
+                    start = Some(i);
+                }
+            }
+            let slots = slots.try_into().map_err(|slots: Vec<u8>| {
+                invalid(
+                    text,
+                    0,
+                    format!("expected 24 slot ids, got {}", slots.len()),
+                )
+            })?;
+            Ok(StateNotation::Slots(slots))
+        } else {
+            let mut colors = Vec::with_capacity(24);
+            for (i, c) in text.char_indices().filter(|(_, c)| !c.is_whitespace()) {
+                let color = COLOR_LETTERS
+                    .iter()
+                    .position(|letter| letter.eq_ignore_ascii_case(&c))
+                    .ok_or_else(|| invalid(text, i, format!("unknown color {}", c)))?;
+                colors.push(color as u8);
+            }
+            let colors = colors.try_into().map_err(|colors: Vec<u8>| {
+                invalid(text, 0, format!("expected 24 colors, got {}", colors.len()))
+            })?;
+            Ok(StateNotation::Colors(colors))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column_of(text: &str) -> usize {
+        match text.parse::<Scramble>() {
+            Err(SolverError::InvalidNotation { column, .. }) => column,
+            other => panic!("expected a notation error for {:?}, got {:?}", text, other),
+        }
+    }
+
+    #[test]
+    fn verbose_and_compact_forms_print_and_parse_back() {
+        let scramble: Scramble = "TL CW; R CCW; R CCW; BL CW; BL CW; BL CW;".parse().unwrap();
+        assert_eq!(scramble.to_string().parse::<Scramble>().unwrap(), scramble);
+        assert_eq!(format!("{:#}", scramble), "TL R2' BL3");
+        assert_eq!(
+            format!("{:#}", scramble).parse::<Scramble>().unwrap(),
+            scramble
+        );
+        let mixed: Scramble = "TL R2'; BL CW; BL2".parse().unwrap();
+        assert_eq!(mixed, scramble);
+    }
+
+    #[test]
+    fn long_runs_are_split_below_the_face_order() {
+        let scramble = Scramble {
+            moves: vec![Move::new(Face::TopLeft, Direction::Clockwise); 7],
+        };
+        assert_eq!(format!("{:#}", scramble), "TL5 TL2");
+        assert_eq!(
+            format!("{:#}", scramble).parse::<Scramble>().unwrap(),
+            scramble
+        );
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_column() {
+        assert_eq!(column_of("TL X"), 4);
+        assert_eq!(column_of("TL; R0"), 6);
+        assert_eq!(column_of("TL R6'"), 5);
+        assert_eq!(column_of("TL99999999999"), 3);
+        assert_eq!(column_of("R R99999999999999999999999"), 4);
+    }
+}
@@ -0,0 +1,103 @@
+pub const SLOT_BITS: u32 = 5;
+pub const COLOR_BITS: u32 = 3;
+/// Bytes needed to store 24 slot ids of `SLOT_BITS` each.
+pub const PACKED_SLOTS_BYTES: usize = 15;
+/// Bytes needed to store 24 colors of `COLOR_BITS` each.
+pub const PACKED_COLORS_BYTES: usize = 9;
+
+/// Packs 24 values of `bits` bits each, slot 0 in the most significant position,
+/// so that comparing packed values compares the arrays lexicographically.
+pub fn pack(values: &[u8; 24], bits: u32) -> u128 {
+    values
+        .iter()
+        .fold(0u128, |packed, &value| (packed << bits) | value as u128)
+}
+
+pub fn unpack(packed: u128, bits: u32) -> [u8; 24] {
+    let mask = (1u128 << bits) - 1;
+    let mut values = [0u8; 24];
+    for (i, value) in values.iter_mut().enumerate() {
+        *value = ((packed >> (bits * (23 - i as u32))) & mask) as u8;
+    }
+    values
+}
+
+/// The low `N` bytes of `packed`, most significant first.
+pub fn packed_to_bytes<const N: usize>(packed: u128) -> [u8; N] {
+    let bytes = packed.to_be_bytes();
+    let mut low = [0u8; N];
+    low.copy_from_slice(&bytes[16 - N..]);
+    low
+}
+
+pub fn packed_from_bytes<const N: usize>(low: &[u8; N]) -> u128 {
+    let mut bytes = [0u8; 16];
+    bytes[16 - N..].copy_from_slice(low);
+    u128::from_be_bytes(bytes)
+}
+
+/// Color in slot `i` of packed colors without unpacking the others.
+pub fn packed_color(colors: u128, i: u8) -> u8 {
+    ((colors >> (COLOR_BITS * (23 - i as u32))) & ((1 << COLOR_BITS) - 1)) as u8
+}
+
+/// Bit of `PackedState::slots` where the score starts; the slot ids only
+/// take the 120 bits below it.
+const SCORE_SHIFT: u32 = 24 * SLOT_BITS;
+
+/// Slot ids and colors of a 24-slot puzzle packed into two integers.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PackedState {
+    /// Compared first, so sorting by the packed state sorts by colors.
+    pub colors: u128,
+    /// The slot ids, and a score in the otherwise unused top byte.
+    pub slots: u128,
+}
+
+impl std::fmt::Debug for PackedState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PackedState")
+            .field("slots", &self.slots())
+            .field("colors", &self.colors())
+            .field("score", &self.score())
+            .finish()
+    }
+}
+
+impl PackedState {
+    pub fn new(slots: &[u8; 24], colors: &[u8; 24]) -> Self {
+        Self {
+            colors: pack(colors, COLOR_BITS),
+            slots: pack(slots, SLOT_BITS),
+        }
+    }
+
+    pub fn slots(&self) -> [u8; 24] {
+        unpack(self.slots, SLOT_BITS)
+    }
+
+    pub fn score(&self) -> u8 {
+        (self.slots >> SCORE_SHIFT) as u8
+    }
+
+    pub fn set_score(&mut self, score: u8) {
+        self.slots = self.slots & ((1 << SCORE_SHIFT) - 1) | (score as u128) << SCORE_SHIFT;
+    }
+
+    pub fn colors(&self) -> [u8; 24] {
+        unpack(self.colors, COLOR_BITS)
+    }
+
+    /// Color in slot `i` without unpacking the others.
+    pub fn color(&self, i: u8) -> u8 {
+        packed_color(self.colors, i)
+    }
+
+    pub fn slots_to_bytes(&self) -> [u8; PACKED_SLOTS_BYTES] {
+        packed_to_bytes(self.slots)
+    }
+
+    pub fn slots_from_bytes(packed: &[u8; PACKED_SLOTS_BYTES]) -> [u8; 24] {
+        unpack(packed_from_bytes(packed), SLOT_BITS)
+    }
+}
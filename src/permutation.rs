@@ -0,0 +1,80 @@
+/// A rearrangement of a puzzle's slots: applying it moves the piece in slot
+/// `self.0[i]` into slot `i`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Permutation(pub Vec<u8>);
+
+impl Permutation {
+    /// Leaves each of `len` slots in place.
+    pub fn identity(len: usize) -> Self {
+        Permutation((0..len as u8).collect())
+    }
+
+    /// Moves the piece in `cycle[i]` to `cycle[i + 1]` and the piece in the
+    /// last slot to the first, leaving the rest of `len` slots in place.
+    pub fn from_cycle(cycle: &[u8], len: usize) -> Self {
+        let mut table = Self::identity(len).0;
+        for i in 0..cycle.len() {
+            table[cycle[(i + 1) % cycle.len()] as usize] = cycle[i];
+        }
+        Permutation(table)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.0
+            .iter()
+            .enumerate()
+            .all(|(i, &source)| source as usize == i)
+    }
+
+    /// Rearranges `slots` with a single gather.
+    pub fn apply<const N: usize>(&self, slots: &mut [u8; N]) {
+        let old = *slots;
+        for (slot, &source) in slots.iter_mut().zip(self.0.iter()) {
+            *slot = old[source as usize];
+        }
+    }
+
+    /// The permutation that applies `self` first and `then` afterwards.
+    pub fn compose(&self, then: &Permutation) -> Self {
+        Permutation(
+            then.0
+                .iter()
+                .map(|&source| self.0[source as usize])
+                .collect(),
+        )
+    }
+
+    pub fn inverse(&self) -> Self {
+        let mut table = vec![0u8; self.len()];
+        for (i, &source) in self.0.iter().enumerate() {
+            table[source as usize] = i as u8;
+        }
+        Permutation(table)
+    }
+
+    /// How often the permutation has to be applied to get back to the identity.
+    pub fn order(&self) -> usize {
+        let mut power = self.clone();
+        let mut order = 1;
+        while !power.is_identity() {
+            power = power.compose(self);
+            order += 1;
+        }
+        order
+    }
+
+    /// Slots whose piece is moved by the permutation.
+    pub fn moved_slots(&self) -> Vec<u8> {
+        (0..self.len() as u8)
+            .filter(|&i| self.0[i as usize] != i)
+            .collect()
+    }
+}
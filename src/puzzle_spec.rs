@@ -0,0 +1,312 @@
+use crate::error::SolverError;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaceSpec {
+    pub name: String,
+    /// Turning the face clockwise moves the piece in `cycle[i]` to `cycle[i + 1]`
+    /// and the piece in the last slot to the first.
+    pub cycle: Vec<u8>,
+}
+
+/// Geometry of a puzzle made of slots that are permuted by face cycles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PuzzleSpec {
+    pub slot_count: usize,
+    pub faces: Vec<FaceSpec>,
+    /// Color of the piece that starts in each slot.
+    pub colors: Vec<u8>,
+    pub neighbours: Vec<Vec<u8>>,
+    /// Pairs of face indices that are turned together when opposite moves are enabled.
+    pub opposite_faces: Vec<(usize, usize)>,
+}
+
+impl PuzzleSpec {
+    /// The 24-slot puzzle that `SinglePuzzle` implements.
+    pub fn builtin() -> Self {
+        builtin_spec().clone()
+    }
+
+    pub fn from_file(path: &str) -> Result<Self, SolverError> {
+        std::fs::read_to_string(path)?.parse()
+    }
+
+    pub fn validate(&self) -> crate::validate::ValidationReport {
+        crate::validate::validate_spec(self)
+    }
+
+    pub fn face_index(&self, name: &str) -> Option<usize> {
+        self.faces.iter().position(|face| face.name == name)
+    }
+
+    pub fn opposite_face(&self, face: usize) -> Option<usize> {
+        self.opposite_faces.iter().find_map(|&(a, b)| {
+            if a == face {
+                Some(b)
+            } else if b == face {
+                Some(a)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// FNV-1a hash of the spec in its `Display` form. Unlike the std
+    /// hashers it is the same on every build, so tables can store it.
+    pub fn fingerprint(&self) -> u64 {
+        self.to_string()
+            .bytes()
+            .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+            })
+    }
+}
+
+/// Writes the spec in the file format, without comments.
+impl fmt::Display for PuzzleSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |values: &[u8]| {
+            values
+                .iter()
+                .map(u8::to_string)
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        writeln!(f, "slots {}", self.slot_count)?;
+        for face in &self.faces {
+            writeln!(f, "face {} {}", face.name, join(&face.cycle))?;
+        }
+        writeln!(f, "colors {}", join(&self.colors))?;
+        for (slot, neighbours) in self.neighbours.iter().enumerate() {
+            if !neighbours.is_empty() {
+                writeln!(f, "neighbours {}: {}", slot, join(neighbours))?;
+            }
+        }
+        for &(a, b) in &self.opposite_faces {
+            writeln!(f, "opposite {} {}", self.faces[a].name, self.faces[b].name)?;
+        }
+        Ok(())
+    }
+}
+
+/// `PuzzleSpec::builtin`, parsed once. `SinglePuzzle`'s face cycles, colors,
+/// neighbours and opposite faces are all read from it.
+pub(crate) fn builtin_spec() -> &'static PuzzleSpec {
+    static BUILTIN: OnceLock<PuzzleSpec> = OnceLock::new();
+    BUILTIN.get_or_init(|| {
+        include_str!("../puzzles/single.puzzle")
+            .parse()
+            .expect("the builtin puzzle spec is valid")
+    })
+}
+
+fn spec_error(line: usize, reason: impl Into<String>) -> SolverError {
+    SolverError::InvalidSpec(format!("line {}: {}", line, reason.into()))
+}
+
+fn parse_slot(token: &str, slot_count: usize, line: usize) -> Result<u8, SolverError> {
+    let slot: usize = token
+        .parse()
+        .map_err(|_| spec_error(line, format!("'{}' is not a slot number", token)))?;
+    if slot >= slot_count {
+        return Err(spec_error(
+            line,
+            format!("slot {} is out of range for {} slots", slot, slot_count),
+        ));
+    }
+    Ok(slot as u8)
+}
+
+impl FromStr for PuzzleSpec {
+    type Err = SolverError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut slot_count = None;
+        let mut faces: Vec<FaceSpec> = Vec::new();
+        let mut colors = None;
+        let mut neighbours: Vec<Vec<u8>> = Vec::new();
+        let mut opposite_names = Vec::new();
+        for (i, raw_line) in text.lines().enumerate() {
+            let line = i + 1;
+            let content = raw_line.split('#').next().unwrap_or("").trim();
+            if content.is_empty() {
+                continue;
+            }
+            let mut tokens = content.split_whitespace();
+            let keyword = tokens.next().unwrap_or("");
+            if keyword != "slots" && slot_count.is_none() {
+                return Err(spec_error(line, "'slots' must come first"));
+            }
+            let count = slot_count.unwrap_or(0);
+            match keyword {
+                "slots" => {
+                    if slot_count.is_some() {
+                        return Err(spec_error(line, "'slots' given twice"));
+                    }
+                    let value = tokens.next().unwrap_or("");
+                    let parsed: usize = value
+                        .parse()
+                        .map_err(|_| spec_error(line, format!("'{}' is not a count", value)))?;
+                    if parsed == 0 || parsed > u8::MAX as usize {
+                        return Err(spec_error(line, "slot count must be between 1 and 255"));
+                    }
+                    slot_count = Some(parsed);
+                    neighbours = vec![Vec::new(); parsed];
+                }
+                "face" => {
+                    let name = tokens
+                        .next()
+                        .ok_or_else(|| spec_error(line, "face without a name"))?;
+                    if faces.iter().any(|face| face.name == name) {
+                        return Err(spec_error(line, format!("face {} given twice", name)));
+                    }
+                    if faces.len() == 128 {
+                        // `SpecMove::to_byte` keeps the face in seven bits
+                        return Err(spec_error(line, "a puzzle has at most 128 faces"));
+                    }
+                    let cycle = tokens
+                        .map(|token| parse_slot(token, count, line))
+                        .collect::<Result<Vec<u8>, SolverError>>()?;
+                    if cycle.len() < 2 {
+                        return Err(spec_error(line, "a face must cycle at least two slots"));
+                    }
+                    if let Some((i, slot)) = cycle
+                        .iter()
+                        .enumerate()
+                        .find(|&(i, slot)| cycle[..i].contains(slot))
+                    {
+                        return Err(spec_error(
+                            line,
+                            format!(
+                                "slot {} appears twice in face {} (position {})",
+                                slot,
+                                name,
+                                i + 1
+                            ),
+                        ));
+                    }
+                    faces.push(FaceSpec {
+                        name: name.to_string(),
+                        cycle,
+                    });
+                }
+                "colors" => {
+                    if colors.is_some() {
+                        return Err(spec_error(line, "'colors' given twice"));
+                    }
+                    let parsed = tokens
+                        .map(|token| {
+                            token.parse::<u8>().map_err(|_| {
+                                spec_error(line, format!("'{}' is not a color", token))
+                            })
+                        })
+                        .collect::<Result<Vec<u8>, SolverError>>()?;
+                    if parsed.len() != count {
+                        return Err(spec_error(
+                            line,
+                            format!("expected {} colors, got {}", count, parsed.len()),
+                        ));
+                    }
+                    colors = Some(parsed);
+                }
+                "neighbours" => {
+                    let slot_token = tokens
+                        .next()
+                        .ok_or_else(|| spec_error(line, "neighbours without a slot"))?;
+                    let slot = parse_slot(slot_token.trim_end_matches(':'), count, line)?;
+                    neighbours[slot as usize] = tokens
+                        .map(|token| parse_slot(token, count, line))
+                        .collect::<Result<Vec<u8>, SolverError>>()?;
+                }
+                "opposite" => {
+                    let pair: Vec<&str> = tokens.collect();
+                    if pair.len() != 2 {
+                        return Err(spec_error(line, "'opposite' takes exactly two faces"));
+                    }
+                    opposite_names.push((line, pair[0].to_string(), pair[1].to_string()));
+                }
+                other => {
+                    return Err(spec_error(line, format!("unknown keyword '{}'", other)));
+                }
+            }
+        }
+        let slot_count = slot_count.ok_or_else(|| spec_error(0, "missing 'slots'"))?;
+        let colors = colors.ok_or_else(|| spec_error(0, "missing 'colors'"))?;
+        if faces.is_empty() {
+            return Err(spec_error(0, "no faces defined"));
+        }
+        let mut spec = PuzzleSpec {
+            slot_count,
+            faces,
+            colors,
+            neighbours,
+            opposite_faces: Vec::new(),
+        };
+        for (line, a, b) in opposite_names {
+            let face_a = spec
+                .face_index(&a)
+                .ok_or_else(|| spec_error(line, format!("unknown face {}", a)))?;
+            let face_b = spec
+                .face_index(&b)
+                .ok_or_else(|| spec_error(line, format!("unknown face {}", b)))?;
+            spec.opposite_faces.push((face_a, face_b));
+        }
+        Ok(spec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn builtin_neighbours_are_symmetric() {
+        let spec = PuzzleSpec::builtin();
+        for (slot, neighbours) in spec.neighbours.iter().enumerate() {
+            for &neighbour in neighbours {
+                assert!(
+                    spec.neighbours[neighbour as usize].contains(&(slot as u8)),
+                    "slot {} lists {}, but not the other way around",
+                    slot,
+                    neighbour
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn builtin_neighbours_are_adjacent_on_a_face_cycle() {
+        let spec = PuzzleSpec::builtin();
+        let mut adjacent = vec![BTreeSet::new(); spec.slot_count];
+        for face in &spec.faces {
+            for (i, &a) in face.cycle.iter().enumerate() {
+                let b = face.cycle[(i + 1) % face.cycle.len()];
+                adjacent[a as usize].insert(b);
+                adjacent[b as usize].insert(a);
+            }
+        }
+        for (slot, neighbours) in spec.neighbours.iter().enumerate() {
+            let listed: BTreeSet<u8> = neighbours.iter().copied().collect();
+            assert_eq!(listed, adjacent[slot], "neighbours of slot {}", slot);
+        }
+    }
+
+    #[test]
+    fn display_parses_back_to_the_same_spec() {
+        let spec = PuzzleSpec::builtin();
+        assert_eq!(spec.to_string().parse::<PuzzleSpec>().unwrap(), spec);
+    }
+
+    #[test]
+    fn rejects_repeated_and_out_of_range_cycle_slots() {
+        let repeated = "slots 4\nface A 0 1 0\ncolors 0 0 0 0\n";
+        assert!(repeated.parse::<PuzzleSpec>().is_err());
+        let out_of_range = "slots 4\nface A 0 1 4\ncolors 0 0 0 0\n";
+        assert!(out_of_range.parse::<PuzzleSpec>().is_err());
+        let valid = "slots 4\nface A 0 1 2\ncolors 0 0 0 0\n";
+        assert!(valid.parse::<PuzzleSpec>().is_ok());
+    }
+}
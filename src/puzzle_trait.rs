@@ -1,12 +1,78 @@
+use crate::canonical::MoveRules;
+use crate::error::SolverError;
+use crate::permutation::Permutation;
 use crate::scramble::Scramble;
 
 pub trait PuzzleTrait: Send + Sync + std::fmt::Debug + Clone + Eq + Ord {
-    fn get_scramble(&self) -> Scramble;
-    fn calculate_score(&self) -> i64;
-    fn apply_scramble(&mut self, scramble: Scramble);
-    fn save_binary_to_file(&self, writer: &mut impl std::io::Write);
-    fn load_binary_from_file(reader: &mut impl std::io::Read, with_opposite_move: bool) -> Option<Self>
+    /// A single turn of the puzzle.
+    type Move: Copy + std::fmt::Debug + Eq + Ord + std::hash::Hash + Send + Sync;
+    /// Everything besides the state itself that is needed to build, turn or
+    /// load a puzzle.
+    type Config: Clone + std::fmt::Debug + Send + Sync;
+
+    /// Names the puzzle type in the header of stored tables; must not change
+    /// once tables exist.
+    const TABLE_ID: &'static str;
+    /// Bits `move_to_byte` needs; a `Record` holds `120 / MOVE_BITS` moves.
+    const MOVE_BITS: u32 = 8;
+
+    /// Everything of `config` that changes the stored states, as one word
+    /// for the header of stored tables.
+    fn encode_config(config: &Self::Config) -> String;
+    /// A state with the goal colors. States only compare by colors, so every
+    /// state equal to this one is solved as well. Moves act on positions, so
+    /// two states with the same colors reach the same colors by the same
+    /// moves: one search from this state covers every slot arrangement of
+    /// the goal, and the ids given to pieces of the same color do not matter.
+    fn solved(config: &Self::Config) -> Self;
+    fn is_solved(&self, config: &Self::Config) -> bool {
+        *self == Self::solved(config)
+    }
+    /// Every move the search may apply, in the order it expands them.
+    fn all_moves(config: &Self::Config) -> Vec<Self::Move>;
+    /// Turns the puzzle by one move, keeping everything derived from the
+    /// state (colors, score) up to date. States do not carry their config,
+    /// so that a batch of millions of them holds it once.
+    fn apply_move(&mut self, mv: Self::Move, config: &Self::Config);
+    fn invert_move(mv: Self::Move) -> Self::Move;
+    /// The slot permutation of every move of `all_moves`, for puzzles whose
+    /// moves permute slots.
+    fn move_tables(_config: &Self::Config) -> Option<Vec<Permutation>> {
+        None
+    }
+    /// Which sequences of `all_moves` the search expands.
+    fn move_rules(config: &Self::Config) -> MoveRules {
+        match Self::move_tables(config) {
+            Some(tables) => MoveRules::from_tables(&tables),
+            None => MoveRules::unrestricted(Self::all_moves(config).len()),
+        }
+    }
+    fn calculate_score(&self, config: &Self::Config) -> i64;
+    /// The score of the current state; puzzles that keep it up to date while
+    /// moving return it without recalculating.
+    fn score(&self, config: &Self::Config) -> i64 {
+        self.calculate_score(config)
+    }
+    /// How much `mv` would change the score.
+    fn score_delta(&self, mv: Self::Move, config: &Self::Config) -> i64 {
+        let mut next = self.clone();
+        next.apply_move(mv, config);
+        next.score(config) - self.score(config)
+    }
+    fn apply_scramble(&mut self, scramble: Scramble<Self::Move>, config: &Self::Config) {
+        for mv in scramble.moves {
+            self.apply_move(mv, config);
+        }
+    }
+    /// One byte that `move_from_byte` turns back into `mv`.
+    fn move_to_byte(mv: Self::Move) -> u8;
+    fn move_from_byte(byte: u8, config: &Self::Config) -> Result<Self::Move, SolverError>;
+    /// Writes the state alone; `Record` stores the moves that reached it.
+    fn write_state(&self, writer: &mut impl std::io::Write) -> Result<(), SolverError>;
+    fn read_state(
+        reader: &mut impl std::io::Read,
+        config: &Self::Config,
+    ) -> Result<Self, SolverError>
     where
         Self: Sized;
-    //fn from_scramble_and_slots(scramble: Option<Scramble>, slots: Vec<u8>, with_opposite_move: bool) -> Self;
 }
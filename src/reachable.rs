@@ -1,14 +1,16 @@
+use crate::canonical::MoveRules;
+use crate::error::SolverError;
+use crate::external_sort::{external_sort, subtract_sorted, SortedCursor};
 use crate::puzzle_trait::PuzzleTrait;
+use crate::record::{Record, MAX_SCORE_WEAKENS};
 use crate::scramble::Scramble;
-use crate::helpers::get_all_moves;
-use std::fs::{File, create_dir_all};
-use std::io::{BufWriter, BufReader};
-use std::marker::PhantomData;
+use std::fs::{create_dir_all, File};
+use std::io::{BufReader, BufWriter, Write};
 
 #[derive(Debug)]
 pub struct Batch<Puzzle: PuzzleTrait> {
     pub batch_size: usize,
-    pub states: Vec<Puzzle>,
+    pub states: Vec<Record<Puzzle>>,
 }
 
 impl<Puzzle: PuzzleTrait> Batch<Puzzle> {
@@ -23,235 +25,365 @@ impl<Puzzle: PuzzleTrait> Batch<Puzzle> {
         self.states.len() >= self.batch_size
     }
 
-    pub fn add_state(&mut self, state: Puzzle) {
-        self.states.push(state);
+    pub fn add_state(&mut self, record: Record<Puzzle>) {
+        self.states.push(record);
     }
 
+    /// Sorts the states and keeps only the shortest scramble of equal ones.
     pub fn sort_states(&mut self) {
-        self.states.sort_by(|a, b| a.cmp(&b));
+        self.states.sort_by(|a, b| a.key().cmp(&b.key()));
+        self.states.dedup_by(|a, b| a.state == b.state);
     }
 
-    pub fn save_to_file(&self, path: &str) {
-        let file = File::create(path).expect("Failed to create batch file");
+    pub fn save_to_file(&self, path: &str) -> Result<(), SolverError> {
+        let file = File::create(path)?;
         let mut writer = BufWriter::new(file);
-        for puzzle in &self.states {
-            puzzle.save_binary_to_file(&mut writer);
+        for record in &self.states {
+            record.save_binary_to_file(&mut writer)?;
         }
+        writer.flush()?;
+        Ok(())
     }
 
-    pub fn load_from_file(
-        path: &str,
-        with_opposite_move: bool,
-    ) -> Self {
-        let file = File::open(path).expect("Failed to open batch file");
+    pub fn load_from_file(path: &str, config: &Puzzle::Config) -> Result<Self, SolverError> {
+        let file = File::open(path)?;
         let mut reader = BufReader::new(file);
         let mut states = Vec::new();
-        while let Some(puzzle) = Puzzle::load_binary_from_file(&mut reader, with_opposite_move) {
-            states.push(puzzle);
+        loop {
+            match Record::load_binary_from_file(&mut reader, config) {
+                Ok(Some(record)) => states.push(record),
+                Ok(None) => break,
+                Err(SolverError::CorruptBatch(reason)) => {
+                    return Err(SolverError::CorruptBatch(format!(
+                        "{}: record {}: {}",
+                        path,
+                        states.len(),
+                        reason
+                    )))
+                }
+                Err(err) => return Err(err),
+            }
         }
-        Batch {
+        Ok(Batch {
             batch_size: states.len(),
             states,
-        }
+        })
     }
 }
 
+/// States reachable from a root puzzle, stored on disk by layer: layer `k`
+/// holds the states first reached with `k` moves, each with one scramble
+/// that reaches it, sorted. Going one move deeper only generates the next
+/// layer from the last one.
 pub struct ReachableStates<Puzzle: PuzzleTrait> {
-    // introduce a marker for puzzle
-    _marker: PhantomData<Puzzle>,
-    pub depth: usize,
+    pub root: Puzzle,
+    /// Number of states per sorted run, and per file of a sorted layer.
     pub batch_size: usize,
-    pub batch_files: Vec<String>,
+    pub merge_fan_in: usize,
+    /// `None` disables the score pruning.
+    pub num_score_weakens: Option<usize>,
+    /// Whether paths have to improve the score (forward side) or may only
+    /// worsen it (solved side) to be expanded.
+    pub improve: bool,
+    /// Sorted files of every layer.
+    pub layers: Vec<Vec<String>>,
+    /// Number of states in every layer.
+    pub layer_sizes: Vec<usize>,
+    /// Number of states generated by `extend`, before duplicates are removed.
+    pub nodes_expanded: usize,
     pub store_directory: String,
-    pub with_opposite_move: bool,
+    pub config: Puzzle::Config,
+    /// Set for tables opened with `open_table`, which must not change.
+    pub read_only: bool,
+    /// For tables, the first state of every file of every layer.
+    pub(crate) index: Vec<Vec<Puzzle>>,
+    pub(crate) all_moves: Vec<Puzzle::Move>,
+    pub(crate) rules: MoveRules,
 }
 
 impl<Puzzle: PuzzleTrait> ReachableStates<Puzzle> {
+    /// Stores the states within `depth` moves of `puzzle`.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         depth: usize,
         puzzle: Puzzle,
+        config: Puzzle::Config,
         batch_size: usize,
         store_directory: String,
-        with_opposite_move: bool,
-        num_score_weakens: usize,
+        num_score_weakens: Option<usize>,
         improve: bool,
-    ) -> Self {
-        create_dir_all(&store_directory).expect("Failed to create store directory");
-        let batch_files = Vec::new();
-        let mut batch = Batch::new(batch_size);
-        let mut batch_count = 0;
+        merge_fan_in: usize,
+    ) -> Result<Self, SolverError> {
+        if num_score_weakens.is_some_and(|k| k > MAX_SCORE_WEAKENS) {
+            return Err(SolverError::InvalidOptions(format!(
+                "at most {} score weakens are supported",
+                MAX_SCORE_WEAKENS
+            )));
+        }
+        let root = puzzle;
         let mut reachable_states = Self {
-            _marker: PhantomData,
-            depth,
             batch_size,
-            batch_files,
-            store_directory: store_directory.clone(),
-            with_opposite_move,
-        };
-        reachable_states.compute_reachable(
-            depth,
-            &get_all_moves(),
-            Scramble { moves: Vec::new() },
-            vec![puzzle.calculate_score()],
+            merge_fan_in,
             num_score_weakens,
             improve,
-            puzzle,
-            &mut batch,
-            &mut batch_count,
-        );
-        if !batch.states.is_empty() {
-            batch.sort_states();
-            let batch_path = format!("{}/batch_{}.bin", store_directory, batch_count);
-            batch.save_to_file(&batch_path);
-            reachable_states.batch_files.push(batch_path);
-            reachable_states.sort_batches();
+            layers: Vec::new(),
+            layer_sizes: Vec::new(),
+            nodes_expanded: 0,
+            store_directory,
+            read_only: false,
+            index: Vec::new(),
+            all_moves: Puzzle::all_moves(&config),
+            rules: Puzzle::move_rules(&config),
+            config,
+            root,
+        };
+        let layer_directory = reachable_states.layer_directory(0);
+        create_dir_all(&layer_directory)?;
+        let root_path = format!("{}/states_0.bin", layer_directory);
+        Batch {
+            batch_size: 1,
+            states: vec![Record::root(reachable_states.root.clone())],
+        }
+        .save_to_file(&root_path)?;
+        reachable_states.layers.push(vec![root_path]);
+        reachable_states.layer_sizes.push(1);
+        while reachable_states.depth() < depth {
+            reachable_states.extend()?;
         }
-        reachable_states
+        Ok(reachable_states)
+    }
+
+    /// Most moves of any stored state.
+    pub fn depth(&self) -> usize {
+        self.layers.len() - 1
     }
 
-    pub fn print_first_5(&self, with_opposite_move: bool) {
-        let mut count = 0;
-        for batch_path in &self.batch_files {
-            let batch = Batch::<Puzzle>::load_from_file(batch_path, with_opposite_move);
-            for state in &batch.states {
-                // Debug print via downcast
-                println!("{:?}", state);
-                count += 1;
-                if count >= 5 {
-                    return;
+    fn layer_directory(&self, layer: usize) -> String {
+        format!("{}/layer_{}", self.store_directory, layer)
+    }
+
+    /// Generates the next layer: every move `MoveRules::next_run` allows
+    /// applied to every unpruned state of the last layer, minus the states
+    /// stored in any earlier layer. Layers are only exact distances without
+    /// pruning; with it, a state pruned on one path can be reached again from
+    /// an older state on another, so every earlier layer is subtracted.
+    pub fn extend(&mut self) -> Result<(), SolverError> {
+        if self.read_only {
+            return Err(SolverError::InvalidTable(format!(
+                "{} is opened read-only and can not be extended",
+                self.store_directory
+            )));
+        }
+        let depth = self.depth();
+        let layer_directory = self.layer_directory(depth + 1);
+        create_dir_all(&layer_directory)?;
+        let mut runs = Vec::new();
+        let mut batch = Batch::new(self.batch_size);
+        let mut frontier = SortedCursor::<Puzzle>::open(&self.layers[depth], &self.config)?;
+        let score_window = self.num_score_weakens.unwrap_or(0);
+        while let Some(record) = frontier.advance()? {
+            if self.is_pruned(&record) {
+                continue;
+            }
+            let last = record.last_move(&self.all_moves);
+            for (i, mv) in self.all_moves.iter().enumerate() {
+                if self.rules.next_run(last, i).is_none() {
+                    continue;
+                }
+                batch.add_state(record.child(*mv, score_window, &self.config)?);
+                self.nodes_expanded += 1;
+                if batch.is_full() {
+                    runs.push(Self::flush_run(&layer_directory, runs.len(), &mut batch)?);
                 }
             }
         }
+        if !batch.states.is_empty() {
+            runs.push(Self::flush_run(&layer_directory, runs.len(), &mut batch)?);
+        }
+        let sorted = external_sort::<Puzzle>(
+            runs,
+            &layer_directory,
+            self.merge_fan_in,
+            self.batch_size,
+            &self.config,
+        )?;
+        let previous: Vec<&[String]> = self.layers.iter().map(Vec::as_slice).collect();
+        let (files, count) = subtract_sorted::<Puzzle>(
+            sorted,
+            &previous,
+            &layer_directory,
+            self.batch_size,
+            &self.config,
+        )?;
+        self.layers.push(files);
+        self.layer_sizes.push(count);
+        Ok(())
     }
 
-    pub fn sort_batches(&mut self) {
-        for j in 0..self.batch_files.len().saturating_sub(1) {
-            let i = self.batch_files.len() - 2 - j;
-            let batch_path_a = &self.batch_files[i];
-            let batch_a = Batch::<Puzzle>::load_from_file(batch_path_a, self.with_opposite_move);
-            let batch_path_b = &self.batch_files[i + 1];
-            let batch_b = Batch::<Puzzle>::load_from_file(batch_path_b, self.with_opposite_move);
-            let mut merged_states = Vec::new();
-            merged_states.extend(batch_a.states);
-            merged_states.extend(batch_b.states);
-            merged_states.sort_by(|a, b| a.cmp(&b));
-            let batch = Batch {
-                states: merged_states[0..batch_a.batch_size].to_vec(),
-                batch_size: batch_a.batch_size,
-            };
-            batch.save_to_file(&batch_path_a);
-            let batch = Batch {
-                states: merged_states[batch_a.batch_size..].to_vec(),
-                batch_size: batch_b.batch_size,
-            };
-            batch.save_to_file(&batch_path_b);
+    /// Whether the score of `record` got worse (or, without `improve`,
+    /// better) than `num_score_weakens` moves before it.
+    fn is_pruned(&self, record: &Record<Puzzle>) -> bool {
+        let Some(num_score_weakens) = self.num_score_weakens else {
+            return false;
+        };
+        let Some(critical_score) = record.scores.oldest(num_score_weakens) else {
+            return false;
+        };
+        let current_score = record.state.score(&self.config);
+        if self.improve {
+            current_score < critical_score
+        } else {
+            current_score > critical_score
         }
     }
 
-    pub fn compute_reachable(
-        &mut self,
-        depth: usize,
-        all_moves: &Vec<crate::single_puzzle::Move>,
-        scramble: Scramble,
-        scores: Vec<i64>,
-        num_score_weakens: usize,
-        improve: bool,
-        puzzle: Puzzle,
+    /// Writes the batch as a sorted run and starts a new one.
+    fn flush_run(
+        directory: &str,
+        run: usize,
         batch: &mut Batch<Puzzle>,
-        batch_count: &mut usize,
-    ) {
-        let mut current_puzzle = puzzle.clone();
-        current_puzzle.apply_scramble(scramble.clone());
-        if improve {
-            if scores.len() > num_score_weakens {
-                let current_score = current_puzzle.calculate_score();
-                let critical_score = scores[scores.len() - 1 - num_score_weakens];
-                if current_score < critical_score {
-                    return;
-                }
-            }
-        } else {
-            if scores.len() > num_score_weakens {
-                let current_score = current_puzzle.calculate_score();
-                let critical_score = scores[scores.len() - 1 - num_score_weakens];
-                if current_score > critical_score {
-                    return;
-                }
+    ) -> Result<String, SolverError> {
+        batch.sort_states();
+        let run_path = format!("{}/run_{}.bin", directory, run);
+        batch.save_to_file(&run_path)?;
+        *batch = Batch::new(batch.batch_size);
+        Ok(run_path)
+    }
+
+    pub fn print_first_5(&self) -> Result<(), SolverError> {
+        let files: Vec<String> = self.layers.iter().flatten().cloned().collect();
+        let mut cursor = SortedCursor::<Puzzle>::open(&files, &self.config)?;
+        for _ in 0..5 {
+            match cursor.advance()? {
+                Some(record) => println!("{:?}", record),
+                None => break,
             }
         }
-        for (i, mv) in all_moves.iter().enumerate() {
-            if depth == self.depth {
-                let progress = (i as f64 / all_moves.len() as f64) * 100.0;
-                println!("Progress: {:.2}%", progress);
-            }
-            if depth == 0 {
-                let mut cloned_puzzle = puzzle.clone();
-                let mut new_scramble = scramble.clone();
-                new_scramble.moves.push(mv.clone());
-                cloned_puzzle.apply_scramble(new_scramble.clone());
+        Ok(())
+    }
 
-                batch.add_state(cloned_puzzle);
-                if batch.is_full() {
-                    batch.sort_states();
-                    let batch_path = format!("{}/batch_{}.bin", self.store_directory, *batch_count);
-                    batch.save_to_file(&batch_path);
-                    self.batch_files.push(batch_path);
-                    self.sort_batches();
-                    *batch = Batch::new(self.batch_size);
-                    *batch_count += 1;
-                }
-            } else {
-                let mut new_scramble = scramble.clone();
-                new_scramble.moves.push(mv.clone());
-                let mut new_puzzle = puzzle.clone();
-                new_puzzle.apply_scramble(new_scramble.clone());
-                let mut new_scores = scores.clone();
-                new_scores.push(new_puzzle.calculate_score());
-                self.compute_reachable(
-                    depth - 1,
-                    all_moves,
-                    new_scramble,
-                    new_scores,
-                    num_score_weakens,
-                    improve,
-                    puzzle.clone(),
-                    batch,
-                    batch_count,
-                );
+    /// Every pair of states from `layer` of this side and `other_layer` of the
+    /// other side that have the same colors. Both layers are streamed in sorted
+    /// order, so each of their files is read exactly once.
+    pub fn overlapping_pairs(
+        &self,
+        layer: usize,
+        other: &Self,
+        other_layer: usize,
+    ) -> Result<OverlapPairs<Puzzle>, SolverError> {
+        Ok(OverlapPairs {
+            left: SortedCursor::open(&self.layers[layer], &self.config)?,
+            right: SortedCursor::open(&other.layers[other_layer], &other.config)?,
+            group: Vec::new(),
+            current: None,
+            next_in_group: 0,
+            failed: false,
+        })
+    }
+
+    /// The moves from this side's root to the other side's root through the
+    /// first overlap among the given pairs of layers, tried in order.
+    pub fn first_overlap(
+        &self,
+        other: &Self,
+        layer_pairs: impl IntoIterator<Item = (usize, usize)>,
+    ) -> Result<Option<Scramble<Puzzle::Move>>, SolverError> {
+        for (layer, other_layer) in layer_pairs {
+            if let Some((record, other_record)) = self
+                .overlapping_pairs(layer, other, other_layer)?
+                .next()
+                .transpose()?
+            {
+                let first_part_of_scramble = record.scramble(&self.config)?;
+                let second_part_of_scramble = other_record
+                    .scramble(&other.config)?
+                    .invert_with(Puzzle::invert_move);
+                return Ok(Some(first_part_of_scramble.concat(second_part_of_scramble)));
             }
         }
+        Ok(None)
     }
 
-    pub fn overlaps(&self, other: &Self, with_opposite_move: bool) -> Option<Scramble> {
-        let mut i_batch = 0;
-        let mut j_batch = 0;
-        let mut i = 0;
-        let mut j = 0;
-        while i_batch < self.batch_files.len() && j_batch < other.batch_files.len() {
-            let batch_a_path = &self.batch_files[i_batch];
-            let batch_a = Batch::<Puzzle>::load_from_file(batch_a_path, with_opposite_move);
-            let batch_b_path = &other.batch_files[j_batch];
-            let batch_b = Batch::<Puzzle>::load_from_file(batch_b_path, with_opposite_move);
-            while i < batch_a.states.len() && j < batch_b.states.len() {
-                match batch_a.states[i].cmp(&batch_b.states[j]) {
-                    std::cmp::Ordering::Equal => {
-                        let first_part_of_scramble = batch_a.states[i].get_scramble();
-                        let second_part_of_scramble = batch_b.states[j].get_scramble().invert();
-                        return Some(first_part_of_scramble.concat(second_part_of_scramble));
+    /// The shortest way from this side's root to the other side's root through
+    /// any state both sides store: layers are joined by increasing total depth.
+    pub fn overlaps(&self, other: &Self) -> Result<Option<Scramble<Puzzle::Move>>, SolverError> {
+        let (depth, other_depth) = (self.depth(), other.depth());
+        let layer_pairs = (0..=depth + other_depth).flat_map(|total| {
+            (total.saturating_sub(other_depth)..=total.min(depth))
+                .map(move |layer| (layer, total - layer))
+        });
+        self.first_overlap(other, layer_pairs)
+    }
+}
+
+/// A record of each side with the same colors.
+pub type OverlapPair<Puzzle> = (Record<Puzzle>, Record<Puzzle>);
+
+/// Merge-join of two sorted sides. States of the right side that share the
+/// current colors are buffered, so memory is bounded by the largest group of
+/// equal states.
+pub struct OverlapPairs<Puzzle: PuzzleTrait> {
+    left: SortedCursor<Puzzle>,
+    right: SortedCursor<Puzzle>,
+    group: Vec<Record<Puzzle>>,
+    current: Option<Record<Puzzle>>,
+    next_in_group: usize,
+    failed: bool,
+}
+
+impl<Puzzle: PuzzleTrait> OverlapPairs<Puzzle> {
+    fn next_pair(&mut self) -> Result<Option<OverlapPair<Puzzle>>, SolverError> {
+        loop {
+            if let Some(current) = &self.current {
+                if let Some(right) = self.group.get(self.next_in_group) {
+                    self.next_in_group += 1;
+                    return Ok(Some((current.clone(), right.clone())));
+                }
+                self.current = None;
+            }
+            let Some(left) = self.left.advance()? else {
+                return Ok(None);
+            };
+            if self.group.first().map(|first| &first.state) != Some(&left.state) {
+                self.group.clear();
+                while self
+                    .right
+                    .peek()
+                    .is_some_and(|right| right.state < left.state)
+                {
+                    self.right.advance()?;
+                }
+                while self
+                    .right
+                    .peek()
+                    .is_some_and(|right| right.state == left.state)
+                {
+                    if let Some(right) = self.right.advance()? {
+                        self.group.push(right);
                     }
-                    std::cmp::Ordering::Less => i += 1,
-                    std::cmp::Ordering::Greater => j += 1,
                 }
             }
-            if i == batch_a.states.len() {
-                i_batch += 1;
-                i = 0;
+            if !self.group.is_empty() {
+                self.current = Some(left);
+                self.next_in_group = 0;
             }
-            if j == batch_b.states.len() {
-                j_batch += 1;
-                j = 0;
+        }
+    }
+}
+
+impl<Puzzle: PuzzleTrait> Iterator for OverlapPairs<Puzzle> {
+    type Item = Result<OverlapPair<Puzzle>, SolverError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        match self.next_pair() {
+            Ok(pair) => pair.map(Ok),
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err))
             }
         }
-        None
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,320 @@
+use crate::error::SolverError;
+use crate::helpers::read_record_part;
+use crate::puzzle_trait::PuzzleTrait;
+use crate::scramble::Scramble;
+
+/// Most moves the score pruning of `ReachableStates` can look back.
+pub const MAX_SCORE_WEAKENS: usize = 8;
+
+/// Marks the entries of `RecentScores` that hold no score yet.
+const NO_SCORE: u16 = u16::MAX;
+
+/// Scores of the states before a record's state on its path, oldest first.
+/// Only as many are kept as the score pruning looks back, so checking it
+/// does not depend on the length of the path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecentScores {
+    /// Filled from the front; the rest holds `NO_SCORE`, which saves storing
+    /// a length in every record.
+    scores: [u16; MAX_SCORE_WEAKENS],
+}
+
+impl Default for RecentScores {
+    fn default() -> Self {
+        Self {
+            scores: [NO_SCORE; MAX_SCORE_WEAKENS],
+        }
+    }
+}
+
+impl RecentScores {
+    /// These scores followed by `score`, keeping the newest `window` of them.
+    pub fn push(&self, score: i64, window: usize) -> Result<Self, SolverError> {
+        let mut next = *self;
+        if window == 0 {
+            return Ok(next);
+        }
+        let score = u16::try_from(score)
+            .ok()
+            .filter(|&score| score != NO_SCORE)
+            .ok_or_else(|| {
+                SolverError::InvalidState(format!("score {} is too large to store", score))
+            })?;
+        let mut len = self.len();
+        if len >= window {
+            next.scores.copy_within(1..window, 0);
+            len = window - 1;
+        }
+        next.scores[len] = score;
+        next.scores[len + 1..].fill(NO_SCORE);
+        Ok(next)
+    }
+
+    /// The score `window` moves before the record's state, once its path is
+    /// that long.
+    pub fn oldest(&self, window: usize) -> Option<i64> {
+        (window > 0 && self.len() == window).then(|| self.scores[0] as i64)
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores
+            .iter()
+            .position(|&score| score == NO_SCORE)
+            .unwrap_or(MAX_SCORE_WEAKENS)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn as_slice(&self) -> &[u16] {
+        &self.scores[..self.len()]
+    }
+}
+
+/// The moves of a record as `PuzzleTrait::move_to_byte` values of
+/// `PuzzleTrait::MOVE_BITS` each, first move in the lowest bits, with their
+/// number in the top byte. Holding them inline keeps a record free of heap
+/// allocations.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct PackedMoves(u128);
+
+impl std::fmt::Debug for PackedMoves {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PackedMoves({:#x})", self.0)
+    }
+}
+
+impl PackedMoves {
+    const LEN_SHIFT: u32 = 120;
+
+    /// Most moves of `bits` bits each that fit.
+    pub fn capacity(bits: u32) -> usize {
+        (Self::LEN_SHIFT / bits) as usize
+    }
+
+    pub fn len(&self) -> usize {
+        (self.0 >> Self::LEN_SHIFT) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Move `i` of `bits` bits.
+    pub fn get(&self, i: usize, bits: u32) -> u8 {
+        ((self.0 >> (i as u32 * bits)) & ((1 << bits) - 1)) as u8
+    }
+
+    /// These moves followed by `byte`, or `None` if there is no room left.
+    pub fn push(&self, byte: u8, bits: u32) -> Option<Self> {
+        let len = self.len();
+        if len >= Self::capacity(bits) || (byte as u128) >> bits != 0 {
+            return None;
+        }
+        let moves = self.0 & ((1 << Self::LEN_SHIFT) - 1);
+        let moves = moves | (byte as u128) << (len as u32 * bits);
+        Some(Self(moves | ((len + 1) as u128) << Self::LEN_SHIFT))
+    }
+
+    pub fn bytes(&self, bits: u32) -> impl Iterator<Item = u8> + '_ {
+        (0..self.len()).map(move |i| self.get(i, bits))
+    }
+
+    /// The last move and how often it was repeated at the end.
+    pub fn last_run(&self, bits: u32) -> Option<(u8, usize)> {
+        let last = self.get(self.len().checked_sub(1)?, bits);
+        let run = (0..self.len())
+            .rev()
+            .take_while(|&i| self.get(i, bits) == last)
+            .count();
+        Some((last, run))
+    }
+}
+
+/// A state stored by the search and the moves that reached it from the root
+/// of its side. Puzzles only hold their state, so the moves of the millions
+/// of states in a batch are kept here and nowhere else.
+#[derive(Debug, Clone)]
+pub struct Record<Puzzle: PuzzleTrait> {
+    pub state: Puzzle,
+    pub moves: PackedMoves,
+    pub scores: RecentScores,
+}
+
+impl<Puzzle: PuzzleTrait> Record<Puzzle> {
+    /// The root of a search, reached without moves.
+    pub fn root(state: Puzzle) -> Self {
+        Self {
+            state,
+            moves: PackedMoves::default(),
+            scores: RecentScores::default(),
+        }
+    }
+
+    /// The record reached by applying `mv` to this one, remembering the
+    /// newest `score_window` scores along the way.
+    pub fn child(
+        &self,
+        mv: Puzzle::Move,
+        score_window: usize,
+        config: &Puzzle::Config,
+    ) -> Result<Self, SolverError> {
+        let moves = self
+            .moves
+            .push(Puzzle::move_to_byte(mv), Puzzle::MOVE_BITS)
+            .ok_or_else(|| {
+                SolverError::InvalidOptions(format!(
+                    "a record holds at most {} moves",
+                    PackedMoves::capacity(Puzzle::MOVE_BITS)
+                ))
+            })?;
+        let scores = self.scores.push(self.state.score(config), score_window)?;
+        let mut state = self.state.clone();
+        state.apply_move(mv, config);
+        Ok(Self {
+            state,
+            moves,
+            scores,
+        })
+    }
+
+    /// The moves that reached the state from the root of its side.
+    pub fn scramble(&self, config: &Puzzle::Config) -> Result<Scramble<Puzzle::Move>, SolverError> {
+        let moves = self
+            .moves
+            .bytes(Puzzle::MOVE_BITS)
+            .map(|byte| Puzzle::move_from_byte(byte, config))
+            .collect::<Result<_, _>>()?;
+        Ok(Scramble { moves })
+    }
+
+    /// The last move, as an index into `all_moves`, and how often it was
+    /// repeated at the end.
+    pub fn last_move(&self, all_moves: &[Puzzle::Move]) -> crate::canonical::LastMove {
+        let (byte, run) = self.moves.last_run(Puzzle::MOVE_BITS)?;
+        let index = all_moves
+            .iter()
+            .position(|&mv| Puzzle::move_to_byte(mv) == byte)?;
+        Some((index, run))
+    }
+
+    /// Sort key of stored records: by state, and of equal states the one
+    /// with the fewest moves first, which is the one deduplication keeps.
+    pub fn key(&self) -> (&Puzzle, usize) {
+        (&self.state, self.moves.len())
+    }
+
+    /// Writes the number of moves, one byte per move, the number of recent
+    /// scores, two bytes per score and then the state.
+    pub fn save_binary_to_file(&self, writer: &mut impl std::io::Write) -> Result<(), SolverError> {
+        writer.write_all(&[self.moves.len() as u8])?;
+        for byte in self.moves.bytes(Puzzle::MOVE_BITS) {
+            writer.write_all(&[byte])?;
+        }
+        let scores = self.scores.as_slice();
+        writer.write_all(&[scores.len() as u8])?;
+        for score in scores {
+            writer.write_all(&score.to_be_bytes())?;
+        }
+        self.state.write_state(writer)
+    }
+
+    /// Returns `Ok(None)` at a clean end of input.
+    pub fn load_binary_from_file(
+        reader: &mut impl std::io::Read,
+        config: &Puzzle::Config,
+    ) -> Result<Option<Self>, SolverError> {
+        let mut moves_len_buf = [0u8; 1];
+        match reader.read_exact(&mut moves_len_buf) {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err.into()),
+        }
+        let moves_len = moves_len_buf[0] as usize;
+        let mut moves = PackedMoves::default();
+        let mut mv_buf = [0u8; 1];
+        for _ in 0..moves_len {
+            read_record_part(reader, &mut mv_buf)?;
+            let byte = Puzzle::move_to_byte(Puzzle::move_from_byte(mv_buf[0], config)?);
+            moves = moves.push(byte, Puzzle::MOVE_BITS).ok_or_else(|| {
+                SolverError::CorruptBatch(format!(
+                    "{} moves, at most {} are kept",
+                    moves_len,
+                    PackedMoves::capacity(Puzzle::MOVE_BITS)
+                ))
+            })?;
+        }
+        let mut scores_len_buf = [0u8; 1];
+        read_record_part(reader, &mut scores_len_buf)?;
+        let scores_len = scores_len_buf[0] as usize;
+        if scores_len > MAX_SCORE_WEAKENS {
+            return Err(SolverError::CorruptBatch(format!(
+                "{} recent scores, at most {} are kept",
+                scores_len, MAX_SCORE_WEAKENS
+            )));
+        }
+        let mut scores = RecentScores::default();
+        let mut score_buf = [0u8; 2];
+        for score in &mut scores.scores[..scores_len] {
+            read_record_part(reader, &mut score_buf)?;
+            *score = u16::from_be_bytes(score_buf);
+            if *score == NO_SCORE {
+                return Err(SolverError::CorruptBatch(format!(
+                    "recent score {} is reserved",
+                    NO_SCORE
+                )));
+            }
+        }
+        let state = Puzzle::read_state(reader, config)?;
+        Ok(Some(Self {
+            state,
+            moves,
+            scores,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color_puzzle::ColorPuzzle;
+    use crate::single_puzzle::SinglePuzzle;
+
+    #[test]
+    fn records_of_the_builtin_puzzles_fit_in_64_bytes() {
+        // a batch of a million records has to stay in the tens of MB
+        assert_eq!(std::mem::size_of::<SinglePuzzle>(), 32);
+        assert_eq!(std::mem::size_of::<Record<SinglePuzzle>>(), 64);
+        assert_eq!(std::mem::size_of::<Record<ColorPuzzle>>(), 64);
+    }
+
+    #[test]
+    fn packed_moves_keep_their_order_and_count() {
+        let mut moves = PackedMoves::default();
+        for byte in [3, 12, 12, 5] {
+            moves = moves.push(byte, 4).unwrap();
+        }
+        assert_eq!(moves.len(), 4);
+        assert_eq!(moves.bytes(4).collect::<Vec<_>>(), [3, 12, 12, 5]);
+        assert_eq!(moves.last_run(4), Some((5, 1)));
+        assert_eq!(moves.push(16, 4), None);
+        let full = (0..PackedMoves::capacity(4)).fold(PackedMoves::default(), |moves, _| {
+            moves.push(15, 4).unwrap()
+        });
+        assert_eq!(full.last_run(4), Some((15, 30)));
+        assert_eq!(full.push(1, 4), None);
+    }
+
+    #[test]
+    fn recent_scores_keep_the_newest_window() {
+        let mut scores = RecentScores::default();
+        for score in 1..=5 {
+            scores = scores.push(score, 3).unwrap();
+        }
+        assert_eq!(scores.as_slice(), [3, 4, 5]);
+        assert_eq!(scores.oldest(3), Some(3));
+        assert_eq!(scores.oldest(2), None);
+        assert!(scores.push(NO_SCORE as i64, 3).is_err());
+    }
+}
@@ -1,25 +1,27 @@
 use crate::single_puzzle::Move;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
-pub struct Scramble {
-    pub moves: Vec<Move>,
+pub struct Scramble<M = Move> {
+    pub moves: Vec<M>,
 }
 
-impl Scramble {
-    pub fn invert(&self) -> Self {
-        let mut inverted_moves = self.moves.clone();
-        inverted_moves.reverse();
-        for mv in &mut inverted_moves {
-            *mv = mv.get_inverted_move();
-        }
+impl<M: Copy> Scramble<M> {
+    /// Reverses the scramble, inverting each move with `invert_move`.
+    pub fn invert_with(&self, invert_move: impl Fn(M) -> M) -> Self {
         Scramble {
-            moves: inverted_moves,
+            moves: self.moves.iter().rev().map(|&mv| invert_move(mv)).collect(),
         }
     }
 
-    pub fn concat(&self, other: Scramble) -> Self {
+    pub fn concat(&self, other: Scramble<M>) -> Self {
         let mut new_moves = self.moves.clone();
         new_moves.extend(other.moves);
         Scramble { moves: new_moves }
     }
 }
+
+impl Scramble<Move> {
+    pub fn invert(&self) -> Self {
+        self.invert_with(|mv| mv.get_inverted_move())
+    }
+}
@@ -0,0 +1,163 @@
+use crate::canonical::{LastMove, MoveRules};
+use crate::error::SolverError;
+use crate::puzzle_trait::PuzzleTrait;
+use crate::scramble::Scramble;
+use crate::single_puzzle::Move;
+use crate::stabilizer_chain::StabilizerChain;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// How `ScrambleGenerator` picks its scrambles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScrambleMode {
+    /// Every move is drawn independently.
+    #[default]
+    Random,
+    /// Every move is drawn from the moves `MoveRules::next_run` allows after
+    /// the previous ones: no move undoes the last one, runs of a face stop
+    /// before the other direction would be shorter, and of two commuting
+    /// faces the lower one comes first.
+    Canonical,
+    /// A uniformly random reachable state is drawn and a scramble reaching
+    /// it is returned; its length follows from the state.
+    RandomState,
+}
+
+/// Draws random scrambles from a seeded RNG, so that the same seed always
+/// gives the same scrambles. `ChaCha8Rng` produces the same numbers on every
+/// platform and release, unlike `StdRng`; only a new major version of `rand`
+/// may change how they are turned into moves.
+#[derive(Debug, Clone)]
+pub struct ScrambleGenerator<M = Move> {
+    seed: u64,
+    rng: ChaCha8Rng,
+    all_moves: Vec<M>,
+    mode: ScrambleMode,
+    rules: MoveRules,
+    /// Only built in `ScrambleMode::RandomState`.
+    chain: Option<StabilizerChain>,
+}
+
+impl<M: Copy> ScrambleGenerator<M> {
+    /// Draws independent moves from `all_moves`.
+    pub fn new(seed: u64, all_moves: Vec<M>) -> Self {
+        Self {
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            rules: MoveRules::unrestricted(all_moves.len()),
+            all_moves,
+            mode: ScrambleMode::Random,
+            chain: None,
+        }
+    }
+
+    /// Draws scrambles of the moves of `P` with `config` in the given mode.
+    /// The random-state mode needs the puzzle's move tables.
+    pub fn for_puzzle<P: PuzzleTrait<Move = M>>(
+        seed: u64,
+        config: &P::Config,
+        mode: ScrambleMode,
+    ) -> Result<Self, SolverError> {
+        let chain = match mode {
+            ScrambleMode::RandomState => {
+                let tables = P::move_tables(config).ok_or_else(|| {
+                    SolverError::InvalidState(
+                        "random states need a puzzle whose moves permute slots".to_string(),
+                    )
+                })?;
+                Some(StabilizerChain::new(tables)?)
+            }
+            ScrambleMode::Random | ScrambleMode::Canonical => None,
+        };
+        Ok(Self {
+            rules: P::move_rules(config),
+            mode,
+            chain,
+            ..Self::new(seed, P::all_moves(config))
+        })
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn mode(&self) -> ScrambleMode {
+        self.mode
+    }
+
+    /// The next scramble; `num_moves` is ignored for random states.
+    pub fn next_scramble(&mut self, num_moves: usize) -> Scramble<M> {
+        let moves = match self.mode {
+            ScrambleMode::Random => (0..num_moves)
+                .map(|_| {
+                    *self
+                        .all_moves
+                        .choose(&mut self.rng)
+                        .expect("a puzzle has at least one move")
+                })
+                .collect(),
+            ScrambleMode::Canonical => {
+                let mut last: LastMove = None;
+                let mut moves = Vec::with_capacity(num_moves);
+                for _ in 0..num_moves {
+                    let allowed: Vec<(usize, usize)> = (0..self.all_moves.len())
+                        .filter_map(|i| self.rules.next_run(last, i).map(|run| (i, run)))
+                        .collect();
+                    let Some(&(i, run)) = allowed.choose(&mut self.rng) else {
+                        break;
+                    };
+                    moves.push(self.all_moves[i]);
+                    last = Some((i, run));
+                }
+                moves
+            }
+            ScrambleMode::RandomState => self
+                .chain
+                .as_ref()
+                .expect("the chain is built for random states")
+                .random_word(&mut self.rng)
+                .into_iter()
+                .map(|i| self.all_moves[i])
+                .collect(),
+        };
+        Scramble { moves }
+    }
+
+    /// The next `count` scrambles of `num_moves` moves each.
+    pub fn scrambles(&mut self, count: usize, num_moves: usize) -> Vec<Scramble<M>> {
+        (0..count).map(|_| self.next_scramble(num_moves)).collect()
+    }
+}
+
+impl ScrambleGenerator<Move> {
+    /// Draws independent moves of the builtin puzzle.
+    pub fn builtin(seed: u64) -> Self {
+        Self::new(seed, crate::helpers::get_all_moves())
+    }
+}
+
+/// A fresh seed for runs that were not given one.
+pub fn random_seed() -> u64 {
+    rand::thread_rng().gen()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::single_puzzle::SinglePuzzle;
+
+    fn scrambles(seed: u64, mode: ScrambleMode) -> Vec<Scramble<Move>> {
+        ScrambleGenerator::for_puzzle::<SinglePuzzle>(seed, &false, mode)
+            .unwrap()
+            .scrambles(5, 20)
+    }
+
+    #[test]
+    fn a_seed_always_draws_the_same_scrambles() {
+        for mode in [ScrambleMode::Random, ScrambleMode::Canonical] {
+            assert_eq!(scrambles(42, mode), scrambles(42, mode));
+            assert_ne!(scrambles(42, mode), scrambles(43, mode));
+        }
+    }
+}
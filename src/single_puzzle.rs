@@ -1,6 +1,12 @@
-use crate::helpers::{calculate_neighbours, get_color};
+use crate::error::SolverError;
+use crate::helpers::{calculate_neighbours, get_color, read_record_part, score_neighbours};
+use crate::packed::{packed_color, PackedState, PACKED_SLOTS_BYTES};
+use crate::permutation::Permutation;
+use crate::puzzle_spec::builtin_spec;
 use crate::puzzle_trait::PuzzleTrait;
 use crate::scramble::Scramble;
+use crate::validate::{validate_tables, FaceTable, ValidationReport};
+use std::sync::OnceLock;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Face {
@@ -12,6 +18,58 @@ pub enum Face {
     BottomRight = 0b110,
 }
 
+impl Face {
+    pub const ALL: [Face; 6] = [
+        Face::TopLeft,
+        Face::Left,
+        Face::BottomLeft,
+        Face::TopRight,
+        Face::Right,
+        Face::BottomRight,
+    ];
+
+    pub fn index(self) -> usize {
+        match self {
+            Face::TopLeft => 0,
+            Face::Left => 1,
+            Face::BottomLeft => 2,
+            Face::TopRight => 3,
+            Face::Right => 4,
+            Face::BottomRight => 5,
+        }
+    }
+
+    /// Index of the face in `PuzzleSpec::builtin`, which names it like
+    /// `helpers::to_string_face`.
+    fn spec_index(self) -> usize {
+        builtin_spec()
+            .face_index(crate::helpers::to_string_face(self))
+            .expect("the builtin spec has every face")
+    }
+
+    /// Clockwise cycle of the face: turning it moves the piece in `cycle[i]`
+    /// to `cycle[i + 1]` and the last piece to the first slot.
+    pub fn cycle(self) -> [u8; 6] {
+        builtin_spec().faces[self.spec_index()]
+            .cycle
+            .as_slice()
+            .try_into()
+            .expect("builtin faces cycle six slots")
+    }
+
+    /// The face turned alongside this one when opposite moves are enabled.
+    pub fn opposite(self) -> Face {
+        let spec = builtin_spec();
+        let opposite = spec
+            .opposite_face(self.spec_index())
+            .expect("every builtin face has an opposite");
+        Face::ALL
+            .into_iter()
+            .find(|&face| crate::helpers::to_string_face(face) == spec.faces[opposite].name)
+            .expect("the builtin spec only names known faces")
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Direction {
     Clockwise = 0b1,
@@ -37,14 +95,7 @@ impl Move {
     }
 
     pub fn get_opposite_move(&self) -> Self {
-        let opposite_face = match self.face {
-            Face::TopLeft => Face::BottomRight,
-            Face::Left => Face::Right,
-            Face::BottomLeft => Face::TopRight,
-            Face::TopRight => Face::BottomLeft,
-            Face::Right => Face::Left,
-            Face::BottomRight => Face::TopLeft,
-        };
+        let opposite_face = self.face.opposite();
         let opposite_direction = match self.direction {
             Direction::Clockwise => Direction::CounterClockwise,
             Direction::CounterClockwise => Direction::Clockwise,
@@ -52,21 +103,141 @@ impl Move {
         Move::new(opposite_face, opposite_direction)
     }
 
-    pub fn to_string(&self) -> String {
-        format!(
-            "{} {};",
-            crate::helpers::to_string_face(self.face),
-            crate::helpers::to_string_direction(self.direction)
-        )
+    /// Packs the move into one byte: the face code followed by the direction bit.
+    pub fn to_byte(&self) -> u8 {
+        ((self.face as u8) << 1) | self.direction as u8
+    }
+
+    pub fn from_byte(byte: u8) -> Result<Self, SolverError> {
+        let face = match byte >> 1 {
+            0b001 => Face::TopLeft,
+            0b010 => Face::Left,
+            0b100 => Face::BottomLeft,
+            0b011 => Face::TopRight,
+            0b101 => Face::Right,
+            0b110 => Face::BottomRight,
+            _ => return Err(SolverError::InvalidMoveByte(byte)),
+        };
+        let direction = match byte & 1 {
+            0b1 => Direction::Clockwise,
+            _ => Direction::CounterClockwise,
+        };
+        Ok(Move::new(face, direction))
+    }
+
+    /// Position of the move in `get_all_moves`.
+    pub fn index(&self) -> usize {
+        match self.direction {
+            Direction::Clockwise => self.face.index() * 2,
+            Direction::CounterClockwise => self.face.index() * 2 + 1,
+        }
+    }
+
+    /// Directed neighbour pairs whose colors the move can change.
+    pub fn score_pairs(&self, with_opposite_move: bool) -> &'static [(u8, u8)] {
+        &score_pairs()[self.index()][with_opposite_move as usize]
+    }
+
+    /// The slot permutation of the move, including the opposite face's turn if requested.
+    pub fn permutation(&self, with_opposite_move: bool) -> &'static Permutation {
+        &move_tables()[self.index()][with_opposite_move as usize]
+    }
+
+    /// Score change of the move on packed colors, looking only at the
+    /// neighbour pairs it touches.
+    pub fn score_delta(&self, colors: u128, with_opposite_move: bool) -> i64 {
+        let permutation = self.permutation(with_opposite_move);
+        let color = |slot: u8| packed_color(colors, slot);
+        let mut delta = 0;
+        for &(slot, neighbour) in self.score_pairs(with_opposite_move) {
+            let before = color(slot) == color(neighbour);
+            let after =
+                color(permutation.0[slot as usize]) == color(permutation.0[neighbour as usize]);
+            delta += after as i64 - before as i64;
+        }
+        delta
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// `PuzzleTrait::move_tables` of the builtin puzzle, in `get_all_moves` order.
+pub(crate) fn builtin_move_tables(with_opposite_move: bool) -> Vec<Permutation> {
+    crate::helpers::get_all_moves()
+        .iter()
+        .map(|mv| mv.permutation(with_opposite_move).clone())
+        .collect()
+}
+
+/// Permutations of every move indexed by `Move::index`, without and with the opposite move.
+fn move_tables() -> &'static [[Permutation; 2]; 12] {
+    static MOVE_TABLES: OnceLock<[[Permutation; 2]; 12]> = OnceLock::new();
+    MOVE_TABLES.get_or_init(|| {
+        let plain = |mv: Move| {
+            let clockwise = Permutation::from_cycle(&mv.face.cycle(), 24);
+            match mv.direction {
+                Direction::Clockwise => clockwise,
+                Direction::CounterClockwise => clockwise.inverse(),
+            }
+        };
+        let mut tables: [[Permutation; 2]; 12] =
+            std::array::from_fn(|_| [Permutation::identity(24), Permutation::identity(24)]);
+        for mv in crate::helpers::get_all_moves() {
+            let permutation = plain(mv);
+            let with_opposite = plain(mv.get_opposite_move()).compose(&permutation);
+            tables[mv.index()] = [permutation, with_opposite];
+        }
+        tables
+    })
+}
+
+/// Directed neighbour pairs `(slot, neighbour)` whose colors a move can change.
+type ScorePairs = Vec<(u8, u8)>;
+
+/// `ScorePairs` of every move, indexed like `move_tables`.
+fn score_pairs() -> &'static [[ScorePairs; 2]; 12] {
+    static SCORE_PAIRS: OnceLock<[[ScorePairs; 2]; 12]> = OnceLock::new();
+    SCORE_PAIRS.get_or_init(|| {
+        std::array::from_fn(|index| {
+            std::array::from_fn(|with_opposite_move| {
+                let moved = move_tables()[index][with_opposite_move].moved_slots();
+                let mut pairs = Vec::new();
+                for slot in 0..24u8 {
+                    let neighbours =
+                        calculate_neighbours(slot).expect("every position has neighbours");
+                    for &neighbour in neighbours {
+                        if moved.contains(&slot) || moved.contains(&neighbour) {
+                            pairs.push((slot, neighbour));
+                        }
+                    }
+                }
+                pairs
+            })
+        })
+    })
+}
+
+/// The builtin puzzle. Whether moves also turn the opposite face is its
+/// `PuzzleTrait::Config`, so a state is just its packed slots and colors,
+/// with `calculate_score` kept up to date by `PuzzleTrait::apply_move` in
+/// the spare bits.
+#[derive(Debug, Clone)]
 pub struct SinglePuzzle {
-    pub with_opposite_move: bool,
-    pub scramble: Option<Scramble>,
-    pub slots: Vec<u8>,
-    pub colors: Vec<u8>,
+    pub state: PackedState,
+}
+
+// Equality, hashing and ordering only look at the packed colors, which is
+// what the meet-in-the-middle join matches on.
+impl PartialEq for SinglePuzzle {
+    fn eq(&self, other: &Self) -> bool {
+        self.state.colors == other.state.colors
+    }
+}
+
+impl Eq for SinglePuzzle {}
+
+impl std::hash::Hash for SinglePuzzle {
+    fn hash<H: std::hash::Hasher>(&self, hasher: &mut H) {
+        self.state.colors.hash(hasher);
+    }
 }
 
 impl PartialOrd for SinglePuzzle {
@@ -77,353 +248,255 @@ impl PartialOrd for SinglePuzzle {
 
 impl Ord for SinglePuzzle {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.colors.cmp(&other.colors)
+        self.state.colors.cmp(&other.state.colors)
     }
 }
 
 impl SinglePuzzle {
-    pub fn get_scramble(&self) -> Scramble {
-        match &self.scramble {
-            Some(scramble) => scramble.clone(),
-            None => Scramble { moves: Vec::new() },
-        }
-    }
-
-    pub fn new_solved(with_opposite_move: bool) -> Self {
-        Self {
-            scramble: None,
-            slots: (0..=23).collect(),
-            colors: (0..=23).map(get_color).collect(),
-            with_opposite_move,
-        }
+    pub fn new_solved() -> Self {
+        let slots: [u8; 24] = std::array::from_fn(|slot| slot as u8);
+        Self::with_state(PackedState::new(&slots, &slots.map(slot_color)))
     }
 
     pub fn new_scrambled(scramble: Scramble, with_opposite_move: bool) -> Self {
-        let mut puzzle = SinglePuzzle {
-            scramble: Some(scramble.clone()),
-            slots: vec![
-                0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22,
-                23,
-            ],
-            colors: (0..=23).map(get_color).collect(),
-            with_opposite_move,
-        };
-        for mv in scramble.moves {
-            puzzle.apply_move(mv, true);
-        }
-        puzzle.deduce_colors();
+        let mut puzzle = SinglePuzzle::new_solved();
+        puzzle.apply_scramble(scramble, &with_opposite_move);
         puzzle
     }
 
-    pub fn calculate_score(&self) -> i64 {
-        let mut score = 0;
-        for (i, &color) in self.colors.iter().enumerate() {
-            let neighbours = calculate_neighbours(i as u8);
-            for &neighbour in neighbours.iter() {
-                if self.colors[neighbour as usize] == color {
-                    score += 1;
-                }
-            }
-        }
-        score
+    /// Fills in the score of `state`.
+    fn with_state(state: PackedState) -> Self {
+        let mut puzzle = Self { state };
+        puzzle.state.set_score(puzzle.calculate_score() as u8);
+        puzzle
     }
 
-    pub fn deduce_colors(&mut self) {
-        self.colors = self.slots.iter().map(|&num| get_color(num)).collect();
+    pub fn slots(&self) -> [u8; 24] {
+        self.state.slots()
     }
 
-    pub fn apply_scramble(&mut self, scramble: Scramble) {
-        self.scramble = Some(scramble.clone());
-        for mv in scramble.moves {
-            self.apply_move(mv, true);
-        }
-        self.colors = self.slots.iter().map(|&num| get_color(num)).collect();
+    pub fn colors(&self) -> [u8; 24] {
+        self.state.colors()
     }
 
-    pub fn apply_move(&mut self, mv: Move, apply_opposite: bool) {
-        if self.with_opposite_move && apply_opposite {
-            let opposite_mv = mv.get_opposite_move();
-            self.apply_move(opposite_mv, false);
-        }
-        // ...existing code for move application...
-        match mv.face {
-            Face::TopLeft => match mv.direction {
-                Direction::Clockwise => {
-                    let first_one = self.slots[5];
-                    self.slots[5] = self.slots[23];
-                    self.slots[23] = self.slots[22];
-                    self.slots[22] = self.slots[20];
-                    self.slots[20] = self.slots[21];
-                    self.slots[21] = self.slots[4];
-                    self.slots[5] = first_one;
-                }
-                Direction::CounterClockwise => {
-                    let first_one = self.slots[5];
-                    self.slots[5] = self.slots[4];
-                    self.slots[4] = self.slots[21];
-                    self.slots[21] = self.slots[20];
-                    self.slots[20] = self.slots[22];
-                    self.slots[22] = self.slots[23];
-                    self.slots[23] = first_one;
-                }
-            },
-            Face::Left => match mv.direction {
-                Direction::Clockwise => {
-                    let first_one = self.slots[20];
-                    self.slots[20] = self.slots[19];
-                    self.slots[19] = self.slots[18];
-                    self.slots[18] = self.slots[16];
-                    self.slots[16] = self.slots[17];
-                    self.slots[17] = self.slots[21];
-                    self.slots[21] = first_one;
-                }
-                Direction::CounterClockwise => {
-                    let first_one = self.slots[20];
-                    self.slots[20] = self.slots[21];
-                    self.slots[21] = self.slots[17];
-                    self.slots[17] = self.slots[16];
-                    self.slots[16] = self.slots[18];
-                    self.slots[18] = self.slots[19];
-                    self.slots[19] = first_one;
-                }
-            },
-            Face::BottomLeft => match mv.direction {
-                Direction::Clockwise => {
-                    let first_one = self.slots[17];
-                    self.slots[17] = self.slots[16];
-                    self.slots[16] = self.slots[15];
-                    self.slots[15] = self.slots[14];
-                    self.slots[14] = self.slots[12];
-                    self.slots[12] = self.slots[13];
-                    self.slots[13] = first_one;
-                }
-                Direction::CounterClockwise => {
-                    let first_one = self.slots[17];
-                    self.slots[17] = self.slots[13];
-                    self.slots[13] = self.slots[12];
-                    self.slots[12] = self.slots[14];
-                    self.slots[14] = self.slots[15];
-                    self.slots[15] = self.slots[16];
-                    self.slots[16] = first_one;
-                }
-            },
-            Face::TopRight => match mv.direction {
-                Direction::Clockwise => {
-                    let first_one = self.slots[0];
-                    self.slots[0] = self.slots[5];
-                    self.slots[5] = self.slots[4];
-                    self.slots[4] = self.slots[3];
-                    self.slots[3] = self.slots[2];
-                    self.slots[2] = self.slots[1];
-                    self.slots[1] = first_one;
-                }
-                Direction::CounterClockwise => {
-                    let first_one = self.slots[0];
-                    self.slots[0] = self.slots[1];
-                    self.slots[1] = self.slots[2];
-                    self.slots[2] = self.slots[3];
-                    self.slots[3] = self.slots[4];
-                    self.slots[4] = self.slots[5];
-                    self.slots[5] = first_one;
-                }
-            },
-            Face::Right => match mv.direction {
-                Direction::Clockwise => {
-                    let first_one = self.slots[2];
-                    self.slots[2] = self.slots[3];
-                    self.slots[3] = self.slots[9];
-                    self.slots[9] = self.slots[8];
-                    self.slots[8] = self.slots[7];
-                    self.slots[7] = self.slots[6];
-                    self.slots[6] = first_one;
-                }
-                Direction::CounterClockwise => {
-                    let first_one = self.slots[2];
-                    self.slots[2] = self.slots[6];
-                    self.slots[6] = self.slots[7];
-                    self.slots[7] = self.slots[8];
-                    self.slots[8] = self.slots[9];
-                    self.slots[9] = self.slots[3];
-                    self.slots[3] = first_one;
-                }
-            },
-            Face::BottomRight => match mv.direction {
-                Direction::Clockwise => {
-                    let first_one = self.slots[9];
-                    self.slots[9] = self.slots[13];
-                    self.slots[13] = self.slots[12];
-                    self.slots[12] = self.slots[11];
-                    self.slots[11] = self.slots[10];
-                    self.slots[10] = self.slots[8];
-                    self.slots[8] = first_one;
-                }
-                Direction::CounterClockwise => {
-                    let first_one = self.slots[9];
-                    self.slots[9] = self.slots[8];
-                    self.slots[8] = self.slots[10];
-                    self.slots[10] = self.slots[11];
-                    self.slots[11] = self.slots[12];
-                    self.slots[12] = self.slots[13];
-                    self.slots[13] = first_one;
-                }
-            },
-        }
+    pub fn calculate_score(&self) -> i64 {
+        score_colors(&self.colors())
     }
 
-    pub fn get_solved_states(with_opposite_move: bool) -> Vec<Self> {
-        let top_area = crate::helpers::permutations(vec![0, 4, 5, 23])
-            .into_iter()
-            .take(4);
-        let top_right_area = crate::helpers::permutations(vec![1, 2, 3, 6])
-            .into_iter()
-            .take(4);
-        let bottom_right_area = crate::helpers::permutations(vec![7, 8, 9, 10])
-            .into_iter()
-            .take(4);
-        let bottom_area = crate::helpers::permutations(vec![11, 12, 13, 14])
-            .into_iter()
-            .take(4);
-        let bottom_left_area = crate::helpers::permutations(vec![15, 16, 17, 18])
-            .into_iter()
-            .take(4);
-        let top_left_area = crate::helpers::permutations(vec![19, 20, 21, 22])
-            .into_iter()
-            .take(4);
-
-        let mut results = Vec::new();
-        for tp in top_area {
-            for trp in top_right_area.clone() {
-                for brp in bottom_right_area.clone() {
-                    for bp in bottom_area.clone() {
-                        for blp in bottom_left_area.clone() {
-                            for tlp in top_left_area.clone() {
-                                let mut puzzle = SinglePuzzle::new_solved(with_opposite_move);
-                                puzzle.apply_cycle(tp.clone());
-                                puzzle.apply_cycle(trp.clone());
-                                puzzle.apply_cycle(brp.clone());
-                                puzzle.apply_cycle(bp.clone());
-                                puzzle.apply_cycle(blp.clone());
-                                puzzle.apply_cycle(tlp.clone());
-                                puzzle.deduce_colors();
-                                results.push(puzzle);
-                            }
-                        }
-                    }
+    /// Checks the hard-coded move cycles, opposite moves and neighbour graph.
+    pub fn validate() -> ValidationReport {
+        let all_moves = crate::helpers::get_all_moves();
+        let faces: Vec<Face> = all_moves
+            .iter()
+            .filter(|mv| mv.direction == Direction::Clockwise)
+            .map(|mv| mv.face)
+            .collect();
+        let table = |mv: Move| {
+            let mut puzzle = SinglePuzzle::new_solved();
+            puzzle.apply_move(mv, &false);
+            puzzle.slots().to_vec()
+        };
+        let face_tables: Vec<FaceTable> = faces
+            .iter()
+            .map(|&face| {
+                let opposite_face = Move::new(face, Direction::Clockwise)
+                    .get_opposite_move()
+                    .face;
+                FaceTable {
+                    name: crate::helpers::to_string_face(face).to_string(),
+                    clockwise: table(Move::new(face, Direction::Clockwise)),
+                    counter_clockwise: table(Move::new(face, Direction::CounterClockwise)),
+                    opposite: faces.iter().position(|&f| f == opposite_face),
                 }
-            }
-        }
-        results
+            })
+            .collect();
+        let neighbours: Vec<Vec<u8>> = (0..24)
+            .map(|i| {
+                calculate_neighbours(i)
+                    .expect("every position has neighbours")
+                    .to_vec()
+            })
+            .collect();
+        validate_tables(24, &face_tables, &neighbours, Some(6))
     }
 
-    pub fn apply_cycle(&mut self, nums: Vec<u8>) {
-        let first_one = nums[0];
-        for i in 0..nums.len() - 1 {
-            self.slots[nums[i] as usize] = nums[i + 1];
+    pub fn from_slots(slots: Vec<u8>) -> Result<Self, SolverError> {
+        validate_slots(&slots)?;
+        let slots: [u8; 24] = slots.try_into().expect("validate_slots checks the length");
+        let mut colors = [0u8; 24];
+        for (color, &num) in colors.iter_mut().zip(slots.iter()) {
+            *color = get_color(num)?;
         }
-        self.slots[*nums.last().unwrap() as usize] = first_one;
-    }
-
-    pub fn from_scramble_and_slots(
-        scramble: Option<Scramble>,
-        slots: Vec<u8>,
-        with_opposite_move: bool,
-    ) -> Self {
-        let mut puzzle = SinglePuzzle {
-            scramble,
-            slots: slots.clone(),
-            colors: vec![0; slots.len()],
-            with_opposite_move,
-        };
-        puzzle.deduce_colors();
-        puzzle
+        Ok(Self::with_state(PackedState::new(&slots, &colors)))
     }
 
-    pub fn save_binary_to_file(&self, writer: &mut impl std::io::Write) {
-        let scramble = self.get_scramble();
-        let moves_len = scramble.moves.len() as u8;
-        writer.write_all(&[moves_len]).unwrap();
-        for mv in scramble.moves.iter() {
-            writer
-                .write_all(&[mv.face as u8, mv.direction as u8])
-                .unwrap();
-        }
-        for slot in self.slots.iter() {
-            writer.write_all(&[*slot]).unwrap();
+    /// Checks that every color appears exactly four times and gives the
+    /// pieces of each color the slot ids of that color in increasing order.
+    pub fn from_colors(colors: [u8; 24]) -> Result<Self, SolverError> {
+        validate_colors(&colors)?;
+        let mut ids_of_color: Vec<Vec<u8>> = vec![Vec::new(); 6];
+        for num in (0..24).rev() {
+            ids_of_color[slot_color(num) as usize].push(num);
         }
-        writer.write_all(b"\n").unwrap();
+        let slots = colors.map(|color| {
+            ids_of_color[color as usize]
+                .pop()
+                .expect("validate_colors checks the counts")
+        });
+        Self::from_slots(slots.to_vec())
     }
 }
 
 impl PuzzleTrait for SinglePuzzle {
-    fn get_scramble(&self) -> Scramble {
-        self.get_scramble()
+    type Move = Move;
+    /// Whether every move also turns the opposite face.
+    type Config = bool;
+
+    const TABLE_ID: &'static str = "slots";
+    const MOVE_BITS: u32 = 4;
+
+    fn encode_config(with_opposite_move: &bool) -> String {
+        crate::helpers::encode_opposite_move(*with_opposite_move).to_string()
+    }
+    fn solved(_with_opposite_move: &bool) -> Self {
+        SinglePuzzle::new_solved()
+    }
+
+    fn all_moves(_with_opposite_move: &bool) -> Vec<Move> {
+        crate::helpers::get_all_moves()
+    }
+    fn apply_move(&mut self, mv: Move, with_opposite_move: &bool) {
+        let score =
+            self.state.score() as i64 + mv.score_delta(self.state.colors, *with_opposite_move);
+        let permutation = mv.permutation(*with_opposite_move);
+        let mut slots = self.slots();
+        let mut colors = self.colors();
+        permutation.apply(&mut slots);
+        permutation.apply(&mut colors);
+        self.state = PackedState::new(&slots, &colors);
+        self.state.set_score(score as u8);
     }
-    fn calculate_score(&self) -> i64 {
+    fn invert_move(mv: Move) -> Move {
+        mv.get_inverted_move()
+    }
+    fn move_tables(with_opposite_move: &bool) -> Option<Vec<Permutation>> {
+        Some(builtin_move_tables(*with_opposite_move))
+    }
+    fn calculate_score(&self, _with_opposite_move: &bool) -> i64 {
         self.calculate_score()
     }
-    fn apply_scramble(&mut self, scramble: Scramble) {
-        self.apply_scramble(scramble)
+    fn score(&self, _with_opposite_move: &bool) -> i64 {
+        self.state.score() as i64
+    }
+    fn score_delta(&self, mv: Move, with_opposite_move: &bool) -> i64 {
+        mv.score_delta(self.state.colors, *with_opposite_move)
+    }
+    fn move_to_byte(mv: Move) -> u8 {
+        mv.to_byte()
+    }
+    fn move_from_byte(byte: u8, _with_opposite_move: &bool) -> Result<Move, SolverError> {
+        Move::from_byte(byte)
     }
-    fn save_binary_to_file(&self, writer: &mut impl std::io::Write) {
-        self.save_binary_to_file(writer)
+    /// Only the slots are stored; the colors follow from them.
+    fn write_state(&self, writer: &mut impl std::io::Write) -> Result<(), SolverError> {
+        writer.write_all(&self.state.slots_to_bytes())?;
+        Ok(())
     }
-    fn load_binary_from_file(
+    fn read_state(
         reader: &mut impl std::io::Read,
-        with_opposite_move: bool,
-    ) -> Option<Self>
+        _with_opposite_move: &bool,
+    ) -> Result<Self, SolverError>
     where
         Self: Sized,
     {
-        read_binary_from_file(reader, with_opposite_move)
+        let mut slots_buf = [0u8; PACKED_SLOTS_BYTES];
+        read_record_part(reader, &mut slots_buf)?;
+        let slots = PackedState::slots_from_bytes(&slots_buf).to_vec();
+        SinglePuzzle::from_slots(slots)
     }
 }
 
-pub fn read_binary_from_file(
-    reader: &mut impl std::io::Read,
-    with_opposite_move: bool,
-) -> Option<SinglePuzzle> {
-    let mut moves_len_buf = [0u8; 1];
-    if reader.read_exact(&mut moves_len_buf).is_err() {
-        return None;
-    }
-    let moves_len = moves_len_buf[0] as usize;
-    let mut moves = Vec::new();
-    let mut mv_buf = [0u8; 2];
-    for _ in 0..moves_len {
-        if reader.read_exact(&mut mv_buf).is_err() {
-            return None;
+/// Number of directed neighbour pairs that show the same color.
+pub fn score_colors(colors: &[u8; 24]) -> i64 {
+    score_neighbours(colors, &builtin_spec().neighbours)
+}
+
+pub(crate) fn slot_color(num: u8) -> u8 {
+    get_color(num).expect("slots are validated on construction")
+}
+
+/// Checks that `slots` is a permutation of the 24 slot ids.
+pub fn validate_slots(slots: &[u8]) -> Result<(), SolverError> {
+    if slots.len() != 24 {
+        return Err(SolverError::InvalidState(format!(
+            "expected 24 slots, got {}",
+            slots.len()
+        )));
+    }
+    let mut seen = [false; 24];
+    for (i, &slot) in slots.iter().enumerate() {
+        match seen.get_mut(slot as usize) {
+            Some(false) => seen[slot as usize] = true,
+            Some(true) => {
+                return Err(SolverError::InvalidState(format!(
+                    "slot id {} appears twice (again at position {})",
+                    slot, i
+                )))
+            }
+            None => {
+                return Err(SolverError::InvalidState(format!(
+                    "slot id {} at position {} is out of range",
+                    slot, i
+                )))
+            }
         }
-        let face = match mv_buf[0] {
-            0b001 => Face::TopLeft,
-            0b010 => Face::Left,
-            0b100 => Face::BottomLeft,
-            0b011 => Face::TopRight,
-            0b101 => Face::Right,
-            0b110 => Face::BottomRight,
-            _ => return None,
-        };
-        let direction = match mv_buf[1] {
-            0b1 => Direction::Clockwise,
-            0b0 => Direction::CounterClockwise,
-            _ => return None,
-        };
-        moves.push(Move::new(face, direction));
-    }
-    let mut slots = vec![0u8; 24];
-    if reader.read_exact(&mut slots).is_err() {
-        return None;
-    }
-    // skip newline if present
-    let mut nl = [0u8; 1];
-    let _ = reader.read_exact(&mut nl);
-    let scramble = if moves.is_empty() {
-        None
-    } else {
-        Some(crate::scramble::Scramble { moves })
-    };
-    Some(SinglePuzzle::from_scramble_and_slots(
-        scramble,
-        slots,
-        with_opposite_move,
-    ))
+    }
+    Ok(())
+}
+
+/// Checks that each of the 6 colors appears exactly four times.
+pub fn validate_colors(colors: &[u8; 24]) -> Result<(), SolverError> {
+    let mut counts = [0usize; 6];
+    for (i, &color) in colors.iter().enumerate() {
+        match counts.get_mut(color as usize) {
+            Some(count) => *count += 1,
+            None => {
+                return Err(SolverError::InvalidState(format!(
+                    "color {} at position {} is out of range",
+                    color, i
+                )))
+            }
+        }
+    }
+    if let Some(color) = counts.iter().position(|&count| count != 4) {
+        return Err(SolverError::InvalidState(format!(
+            "color {} appears {} times instead of 4",
+            color, counts[color]
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn the_kept_score_matches_a_recalculation() {
+        let mut rng = ChaCha8Rng::seed_from_u64(9);
+        for with_opposite_move in [false, true] {
+            let moves = SinglePuzzle::all_moves(&with_opposite_move);
+            let mut puzzle = SinglePuzzle::new_solved();
+            for _ in 0..200 {
+                let mv = moves[rng.gen_range(0..moves.len())];
+                let expected =
+                    puzzle.score(&with_opposite_move) + puzzle.score_delta(mv, &with_opposite_move);
+                puzzle.apply_move(mv, &with_opposite_move);
+                assert_eq!(puzzle.score(&with_opposite_move), puzzle.calculate_score());
+                assert_eq!(puzzle.score(&with_opposite_move), expected);
+            }
+        }
+    }
 }
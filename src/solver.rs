@@ -0,0 +1,333 @@
+use crate::error::SolverError;
+use crate::puzzle_trait::PuzzleTrait;
+use crate::reachable::ReachableStates;
+use crate::scramble::Scramble;
+use crate::single_puzzle::Move;
+
+#[derive(Debug, Clone)]
+pub struct SolveOptions {
+    /// States per sorted run written while generating.
+    pub batch_size: usize,
+    /// How many runs are merged at once when sorting them.
+    pub merge_fan_in: usize,
+    pub num_score_weakens: usize,
+    /// Disables the score pruning and looks for the shortest solution, so
+    /// that a solution is optimal and a failed depth proves a lower bound.
+    pub exact: bool,
+    /// Moves searched from each side in the first iteration, or the side's
+    /// maximum if that is lower. Both sides grow one layer at a time and keep
+    /// their layers, so starting low only costs the joins of the shallow
+    /// layers.
+    pub start_depth: usize,
+    /// Most moves searched from the scrambled puzzle.
+    pub max_forward_depth: usize,
+    /// Most moves searched from the solved puzzle. A solved-side table is
+    /// joined at the depth it was built with instead.
+    pub max_backward_depth: usize,
+    pub store_directory: String,
+    /// A table written by `Solver::build_table` to use as the solved side
+    /// instead of searching from the solved puzzle.
+    pub solved_table: Option<String>,
+    /// Suppresses the progress output.
+    pub quiet: bool,
+}
+
+impl Default for SolveOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: 1_000_000,
+            merge_fan_in: 16,
+            num_score_weakens: 3,
+            exact: false,
+            start_depth: 0,
+            max_forward_depth: 13,
+            max_backward_depth: 13,
+            store_directory: "reachable_batches".to_string(),
+            solved_table: None,
+            quiet: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SolveReport<M = Move> {
+    /// Moves that bring the given puzzle back to the solved colors.
+    pub solution: Option<Scramble<M>>,
+    /// Forward and backward depths that were searched, in order. A puzzle
+    /// found directly in a solved-side table is recorded as searched at
+    /// forward depth 0 and the layer of the table holding it.
+    pub depths_searched: Vec<(usize, usize)>,
+    /// Whether `solution` is known to be as short as possible.
+    pub optimal: bool,
+    /// In exact mode, the fewest moves any solution can have, as proven by
+    /// each pair of depths that failed, in order.
+    pub lower_bounds: Vec<usize>,
+    /// States generated by both sides, before duplicates were removed.
+    pub nodes_expanded: usize,
+}
+
+impl<M> SolveReport<M> {
+    pub fn is_solved(&self) -> bool {
+        self.solution.is_some()
+    }
+}
+
+pub struct Solver;
+
+impl Solver {
+    /// Runs the meet-in-the-middle search for increasing depths until a
+    /// solution is found or both sides reached their maximum depth. Every
+    /// iteration adds one move to one side, (d, d) -> (d + 1, d) ->
+    /// (d + 1, d + 1), so no solution length is skipped; once one side is at
+    /// its maximum only the other one grows. In exact mode the solution found is the
+    /// shortest one.
+    pub fn solve<P: PuzzleTrait>(
+        puzzle: &P,
+        config: &P::Config,
+        options: SolveOptions,
+    ) -> Result<SolveReport<P::Move>, SolverError> {
+        Self::solve_scramble(puzzle, &Scramble { moves: Vec::new() }, config, options)
+    }
+
+    /// Like `solve`, for `start` with `scramble` applied. The scramble is
+    /// only kept to replay it when verifying the solution.
+    pub fn solve_scramble<P: PuzzleTrait>(
+        start: &P,
+        scramble: &Scramble<P::Move>,
+        config: &P::Config,
+        options: SolveOptions,
+    ) -> Result<SolveReport<P::Move>, SolverError> {
+        let mut puzzle = start.clone();
+        puzzle.apply_scramble(scramble.clone(), config);
+        let mut report = SolveReport {
+            solution: None,
+            depths_searched: Vec::new(),
+            optimal: false,
+            lower_bounds: Vec::new(),
+            nodes_expanded: 0,
+        };
+        if puzzle.is_solved(config) {
+            report.solution = Some(Scramble { moves: Vec::new() });
+            report.optimal = true;
+            return Ok(report);
+        }
+        let store_directory = options.store_directory.clone();
+        let solved_store_directory = format!("{}_solved", store_directory);
+        let result = Self::search(
+            &puzzle,
+            scramble,
+            config,
+            &options,
+            &store_directory,
+            &solved_store_directory,
+            &mut report,
+        );
+        std::fs::remove_dir_all(&store_directory).ok();
+        std::fs::remove_dir_all(&solved_store_directory).ok();
+        result?;
+        if let Some(solution) = &report.solution {
+            Self::verify_solution(start, scramble, solution, config)?;
+        }
+        Ok(report)
+    }
+
+    /// Replays `scramble` followed by `solution` on a fresh copy of `start`
+    /// and checks that it ends up solved.
+    pub fn verify_solution<P: PuzzleTrait>(
+        start: &P,
+        scramble: &Scramble<P::Move>,
+        solution: &Scramble<P::Move>,
+        config: &P::Config,
+    ) -> Result<(), SolverError> {
+        let mut reached = start.clone();
+        reached.apply_scramble(scramble.concat(solution.clone()), config);
+        if reached.is_solved(config) {
+            return Ok(());
+        }
+        Err(SolverError::InvalidSolution(format!(
+            "{} moves do not solve the puzzle\n  scramble: {:?}\n  solution: {:?}\n  start:    {:?}\n  reached:  {:?}\n  solved:   {:?}",
+            solution.moves.len(),
+            scramble.moves,
+            solution.moves,
+            start,
+            reached,
+            P::solved(config)
+        )))
+    }
+
+    /// Searches from the solved puzzle of `config` up to `depth` moves and
+    /// keeps the result in `directory` as a table for `SolveOptions::solved_table`.
+    /// The table is built with the pruning settings of `options`.
+    pub fn build_table<P: PuzzleTrait>(
+        config: &P::Config,
+        depth: usize,
+        directory: &str,
+        options: &SolveOptions,
+    ) -> Result<ReachableStates<P>, SolverError> {
+        let exists = std::fs::read_dir(directory).is_ok_and(|mut entries| entries.next().is_some());
+        if exists {
+            return Err(SolverError::InvalidTable(format!(
+                "{} already exists and is not empty",
+                directory
+            )));
+        }
+        let num_score_weakens = (!options.exact).then_some(options.num_score_weakens);
+        let table = ReachableStates::new(
+            depth,
+            P::solved(config),
+            config.clone(),
+            options.batch_size,
+            directory.to_string(),
+            num_score_weakens,
+            false,
+            options.merge_fan_in,
+        )?;
+        table.save_table()?;
+        Ok(table)
+    }
+
+    /// Both sides are kept between iterations and grow by one layer at a
+    /// time; only the new layer is joined with the other side. A solved-side
+    /// table never grows, so only the scrambled side does.
+    fn search<P: PuzzleTrait>(
+        puzzle: &P,
+        scramble: &Scramble<P::Move>,
+        config: &P::Config,
+        options: &SolveOptions,
+        store_directory: &str,
+        solved_store_directory: &str,
+        report: &mut SolveReport<P::Move>,
+    ) -> Result<(), SolverError> {
+        let num_score_weakens = (!options.exact).then_some(options.num_score_weakens);
+        let solved_state = P::solved(config);
+        let table = match &options.solved_table {
+            Some(table) => Some(ReachableStates::open_table(
+                table,
+                solved_state.clone(),
+                config.clone(),
+                num_score_weakens,
+                false,
+            )?),
+            None => None,
+        };
+        if let Some(table) = &table {
+            // Nothing is generated for a puzzle the table holds, so no nodes
+            // are expanded. An exact table stores every state in the layer of
+            // its distance from the solved puzzle, so in exact mode the stored
+            // moves are as short as possible.
+            if let Some((layer, stored)) = table.lookup(puzzle)? {
+                report.depths_searched.push((0, layer));
+                report.solution = Some(stored.scramble(config)?.invert_with(P::invert_move));
+                report.optimal = options.exact;
+                return Ok(());
+            }
+        }
+        let mut reachable_states = ReachableStates::new(
+            options.start_depth.min(options.max_forward_depth),
+            puzzle.clone(),
+            config.clone(),
+            options.batch_size,
+            store_directory.to_string(),
+            num_score_weakens,
+            true,
+            options.merge_fan_in,
+        )?;
+        if !options.quiet {
+            print_layers("Forward", &reachable_states, 0);
+            reachable_states.print_first_5()?;
+        }
+        let mut reachable_from_solved = match table {
+            Some(table) => table,
+            None => ReachableStates::new(
+                options.start_depth.min(options.max_backward_depth),
+                solved_state,
+                config.clone(),
+                options.batch_size,
+                solved_store_directory.to_string(),
+                num_score_weakens,
+                false,
+                options.merge_fan_in,
+            )?,
+        };
+        if !options.quiet && !reachable_from_solved.read_only {
+            print_layers("Backward", &reachable_from_solved, 0);
+        }
+        let mut solution = reachable_states.overlaps(&reachable_from_solved)?;
+        loop {
+            let (forward, backward) = (reachable_states.depth(), reachable_from_solved.depth());
+            if !options.quiet {
+                println!(
+                    "Depths: {} forward, {} backward, Scramble: {:?}",
+                    forward, backward, scramble
+                );
+            }
+            report.depths_searched.push((forward, backward));
+            if solution.is_some() {
+                report.solution = solution;
+                report.optimal = options.exact;
+                break;
+            }
+            if options.exact {
+                // the sides hold every state within forward and backward moves
+                report.lower_bounds.push(forward + backward + 1);
+            }
+            let grow_forward = forward < options.max_forward_depth;
+            let grow_backward =
+                backward < options.max_backward_depth && !reachable_from_solved.read_only;
+            if grow_backward && (forward > backward || !grow_forward) {
+                reachable_from_solved.extend()?;
+                if !options.quiet {
+                    print_layers("Backward", &reachable_from_solved, backward + 1);
+                }
+                solution = reachable_states.first_overlap(
+                    &reachable_from_solved,
+                    (0..=forward).map(|i| (i, backward + 1)),
+                )?;
+            } else if grow_forward {
+                reachable_states.extend()?;
+                if !options.quiet {
+                    print_layers("Forward", &reachable_states, forward + 1);
+                }
+                solution = reachable_states.first_overlap(
+                    &reachable_from_solved,
+                    (0..=backward).map(|j| (forward + 1, j)),
+                )?;
+            } else {
+                break;
+            }
+        }
+        report.nodes_expanded =
+            reachable_states.nodes_expanded + reachable_from_solved.nodes_expanded;
+        Ok(())
+    }
+}
+
+fn print_layers<P: PuzzleTrait>(side: &str, states: &ReachableStates<P>, from: usize) {
+    for (layer, size) in states.layer_sizes.iter().enumerate().skip(from) {
+        println!("{} layer {}: {} states", side, layer, size);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::single_puzzle::{Direction, Face, Move, SinglePuzzle};
+
+    #[test]
+    fn only_a_complete_solution_verifies() {
+        let start = SinglePuzzle::new_solved();
+        let scramble: Scramble = "L CW; R CW; TL CCW;".parse().unwrap();
+        let solution = scramble.invert();
+        assert!(Solver::verify_solution(&start, &scramble, &solution, &false).is_ok());
+        let mut truncated = solution.clone();
+        truncated.moves.pop();
+        let mut corrupted = solution;
+        corrupted.moves[0] = Move::new(Face::Right, Direction::Clockwise);
+        for wrong in [truncated, corrupted] {
+            assert!(matches!(
+                Solver::verify_solution(&start, &scramble, &wrong, &false),
+                Err(SolverError::InvalidSolution(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use crate::error::SolverError;
+use crate::helpers::read_record_part;
+use crate::permutation::Permutation;
+use crate::puzzle_spec::PuzzleSpec;
+use crate::puzzle_trait::PuzzleTrait;
+use crate::scramble::Scramble;
+use crate::single_puzzle::Direction;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SpecMove {
+    /// Index into `PuzzleSpec::faces`.
+    pub face: u8,
+    pub direction: Direction,
+}
+
+impl SpecMove {
+    pub fn new(face: u8, direction: Direction) -> Self {
+        Self { face, direction }
+    }
+
+    pub fn get_inverted_move(&self) -> Self {
+        match self.direction {
+            Direction::Clockwise => SpecMove::new(self.face, Direction::CounterClockwise),
+            Direction::CounterClockwise => SpecMove::new(self.face, Direction::Clockwise),
+        }
+    }
+
+    /// Packs the move into one byte like `Move::to_byte`: the face index
+    /// followed by the direction bit. A spec has at most 128 faces.
+    pub fn to_byte(&self) -> u8 {
+        (self.face << 1) | self.direction as u8
+    }
+
+    /// Checks that the face exists in a spec of `face_count` faces.
+    pub fn from_byte(byte: u8, face_count: usize) -> Result<Self, SolverError> {
+        let face = byte >> 1;
+        if face as usize >= face_count {
+            return Err(SolverError::InvalidMoveByte(byte));
+        }
+        let direction = match byte & 1 {
+            0b1 => Direction::Clockwise,
+            _ => Direction::CounterClockwise,
+        };
+        Ok(SpecMove::new(face, direction))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpecPuzzleConfig {
+    pub spec: Arc<PuzzleSpec>,
+    pub with_opposite_move: bool,
+}
+
+impl SpecPuzzleConfig {
+    pub fn get_all_moves(&self) -> Vec<SpecMove> {
+        let mut all_moves = Vec::new();
+        for face in 0..self.spec.faces.len() as u8 {
+            for direction in [Direction::Clockwise, Direction::CounterClockwise] {
+                all_moves.push(SpecMove::new(face, direction));
+            }
+        }
+        all_moves
+    }
+
+    pub fn get_opposite_move(&self, mv: SpecMove) -> Option<SpecMove> {
+        self.spec
+            .opposite_face(mv.face as usize)
+            .map(|face| SpecMove::new(face as u8, mv.get_inverted_move().direction))
+    }
+
+    /// Formats `mv` like `Move`'s display form, using the face names from the spec.
+    pub fn format_move(&self, mv: SpecMove) -> String {
+        format!(
+            "{} {};",
+            self.spec.faces[mv.face as usize].name,
+            crate::helpers::to_string_direction(mv.direction)
+        )
+    }
+}
+
+/// A puzzle whose geometry comes from a `PuzzleSpec` instead of being
+/// compiled in. The spec is part of the `SpecPuzzleConfig`, the state only
+/// holds the slots and their colors.
+#[derive(Debug, Clone)]
+pub struct SpecPuzzle {
+    pub slots: Vec<u8>,
+    pub colors: Vec<u8>,
+}
+
+// Like `SinglePuzzle`, equality and ordering only look at the colors.
+impl PartialEq for SpecPuzzle {
+    fn eq(&self, other: &Self) -> bool {
+        self.colors == other.colors
+    }
+}
+
+impl Eq for SpecPuzzle {}
+
+impl PartialOrd for SpecPuzzle {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SpecPuzzle {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.colors.cmp(&other.colors)
+    }
+}
+
+impl SpecPuzzle {
+    pub fn new_solved(spec: &PuzzleSpec) -> Self {
+        Self {
+            slots: (0..spec.slot_count as u8).collect(),
+            colors: spec.colors.clone(),
+        }
+    }
+
+    pub fn new_scrambled(config: &SpecPuzzleConfig, scramble: Scramble<SpecMove>) -> Self {
+        let mut puzzle = SpecPuzzle::new_solved(&config.spec);
+        puzzle.apply_scramble(scramble, config);
+        puzzle
+    }
+
+    pub fn calculate_score(&self, spec: &PuzzleSpec) -> i64 {
+        crate::helpers::score_neighbours(&self.colors, &spec.neighbours)
+    }
+
+    /// Cycles the slots and colors of `mv`'s face, and of its opposite face
+    /// if enabled and `apply_opposite` is set.
+    fn turn(&mut self, mv: SpecMove, config: &SpecPuzzleConfig, apply_opposite: bool) {
+        if config.with_opposite_move && apply_opposite {
+            if let Some(opposite_mv) = config.get_opposite_move(mv) {
+                self.turn(opposite_mv, config, false);
+            }
+        }
+        let cycle = &config.spec.faces[mv.face as usize].cycle;
+        cycle_values(&mut self.slots, cycle, mv.direction);
+        cycle_values(&mut self.colors, cycle, mv.direction);
+    }
+
+    pub fn from_slots(slots: Vec<u8>, config: &SpecPuzzleConfig) -> Result<Self, SolverError> {
+        let slot_count = config.spec.slot_count;
+        if slots.len() != slot_count {
+            return Err(SolverError::InvalidState(format!(
+                "expected {} slots, got {}",
+                slot_count,
+                slots.len()
+            )));
+        }
+        let mut seen = vec![false; slot_count];
+        for (i, &slot) in slots.iter().enumerate() {
+            if slot as usize >= slot_count || seen[slot as usize] {
+                return Err(SolverError::InvalidState(format!(
+                    "slot id {} at position {} is out of range or repeated",
+                    slot, i
+                )));
+            }
+            seen[slot as usize] = true;
+        }
+        let colors = slots
+            .iter()
+            .map(|&num| config.spec.colors[num as usize])
+            .collect();
+        Ok(SpecPuzzle { slots, colors })
+    }
+}
+
+/// Moves the values at the slots of `cycle` one step along it.
+fn cycle_values(values: &mut [u8], cycle: &[u8], direction: Direction) {
+    let at = |i: usize| cycle[i] as usize;
+    match direction {
+        Direction::Clockwise => {
+            let last_one = values[at(cycle.len() - 1)];
+            for i in (1..cycle.len()).rev() {
+                values[at(i)] = values[at(i - 1)];
+            }
+            values[at(0)] = last_one;
+        }
+        Direction::CounterClockwise => {
+            let first_one = values[at(0)];
+            for i in 0..cycle.len() - 1 {
+                values[at(i)] = values[at(i + 1)];
+            }
+            values[at(cycle.len() - 1)] = first_one;
+        }
+    }
+}
+
+impl PuzzleTrait for SpecPuzzle {
+    type Move = SpecMove;
+    type Config = SpecPuzzleConfig;
+
+    const TABLE_ID: &'static str = "spec";
+
+    /// The spec's fingerprint and whether opposite faces turn together.
+    fn encode_config(config: &SpecPuzzleConfig) -> String {
+        format!(
+            "{:016x}-{}",
+            config.spec.fingerprint(),
+            crate::helpers::encode_opposite_move(config.with_opposite_move)
+        )
+    }
+    fn solved(config: &SpecPuzzleConfig) -> Self {
+        SpecPuzzle::new_solved(&config.spec)
+    }
+    fn all_moves(config: &SpecPuzzleConfig) -> Vec<SpecMove> {
+        config.get_all_moves()
+    }
+    fn apply_move(&mut self, mv: SpecMove, config: &SpecPuzzleConfig) {
+        self.turn(mv, config, true);
+    }
+    fn invert_move(mv: SpecMove) -> SpecMove {
+        mv.get_inverted_move()
+    }
+    fn move_tables(config: &SpecPuzzleConfig) -> Option<Vec<Permutation>> {
+        let solved = SpecPuzzle::new_solved(&config.spec);
+        let tables = config
+            .get_all_moves()
+            .into_iter()
+            .map(|mv| {
+                let mut moved = solved.clone();
+                moved.turn(mv, config, true);
+                Permutation(moved.slots)
+            })
+            .collect();
+        Some(tables)
+    }
+    fn calculate_score(&self, config: &SpecPuzzleConfig) -> i64 {
+        self.calculate_score(&config.spec)
+    }
+    fn move_to_byte(mv: SpecMove) -> u8 {
+        mv.to_byte()
+    }
+    fn move_from_byte(byte: u8, config: &SpecPuzzleConfig) -> Result<SpecMove, SolverError> {
+        SpecMove::from_byte(byte, config.spec.faces.len())
+    }
+    /// One byte per slot and a `\n`, which catches records read at the wrong offset.
+    fn write_state(&self, writer: &mut impl std::io::Write) -> Result<(), SolverError> {
+        writer.write_all(&self.slots)?;
+        writer.write_all(b"\n")?;
+        Ok(())
+    }
+    fn read_state(
+        reader: &mut impl std::io::Read,
+        config: &SpecPuzzleConfig,
+    ) -> Result<Self, SolverError>
+    where
+        Self: Sized,
+    {
+        let mut slots = vec![0u8; config.spec.slot_count];
+        read_record_part(reader, &mut slots)?;
+        let mut nl = [0u8; 1];
+        read_record_part(reader, &mut nl)?;
+        if nl[0] != b'\n' {
+            return Err(SolverError::CorruptBatch(format!(
+                "expected record terminator, found {:#04x}",
+                nl[0]
+            )));
+        }
+        SpecPuzzle::from_slots(slots, config)
+    }
+}
@@ -0,0 +1,363 @@
+use crate::error::SolverError;
+use crate::permutation::Permutation;
+use rand::Rng;
+use std::collections::HashSet;
+
+/// Length of the words enumerated up front; their entries are short and the
+/// closure only has to fill in what they miss.
+const SHORT_WORD_LENGTH: usize = 5;
+/// Rounds of `StabilizerChain::improve` after the chain is complete.
+const IMPROVE_ROUNDS: usize = 2;
+
+/// Every state reachable with a set of moves, as a stabilizer chain
+/// (Schreier-Sims) whose entries also carry a word of moves reaching them.
+///
+/// Slots are fixed in order: `entries[level][slot]` fixes the pieces of
+/// slots `0..level` and brings the piece of slot `level` to `slot`. Every
+/// reachable state is the product of exactly one entry per level, so picking
+/// each entry uniformly samples a uniformly random reachable state. Of the
+/// words found for an entry the shortest is kept (Minkwitz), which keeps the
+/// sampled scrambles from growing with every level.
+#[derive(Debug, Clone)]
+pub struct StabilizerChain {
+    /// Tables of the moves as in `PuzzleTrait::move_tables`.
+    tables: Vec<Permutation>,
+    /// Moves undoing each move, a single one if the puzzle has it.
+    inverses: Vec<Vec<usize>>,
+    orders: Vec<usize>,
+    /// Whether entries carry words; without them the chain only counts states.
+    with_words: bool,
+    entries: Vec<Vec<Option<Entry>>>,
+    entry_count: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    table: Permutation,
+    /// Indices into the move tables, applied in order.
+    word: Vec<usize>,
+}
+
+impl StabilizerChain {
+    pub fn new(tables: Vec<Permutation>) -> Result<Self, SolverError> {
+        if tables.is_empty() {
+            return Err(SolverError::InvalidState(
+                "a puzzle without moves has no random states".to_string(),
+            ));
+        }
+        // Sifting words is slow, so the plain permutations are closed first to
+        // learn how many entries the chain needs, and the words stop there.
+        let mut counting = Self::empty(tables.clone(), false);
+        counting.close(usize::MAX);
+        let mut chain = Self::empty(tables, true);
+        chain.add_short_words();
+        chain.close(counting.entry_count);
+        for _ in 0..IMPROVE_ROUNDS {
+            chain.improve();
+        }
+        Ok(chain)
+    }
+
+    fn empty(tables: Vec<Permutation>, with_words: bool) -> Self {
+        let slot_count = tables[0].len();
+        let orders: Vec<usize> = tables.iter().map(Permutation::order).collect();
+        let inverses = (0..tables.len())
+            .map(|i| {
+                let inverse = tables[i].inverse();
+                match tables.iter().position(|table| *table == inverse) {
+                    Some(j) => vec![j],
+                    None => vec![i; orders[i] - 1],
+                }
+            })
+            .collect();
+        Self {
+            tables,
+            inverses,
+            orders,
+            with_words,
+            entries: (0..slot_count)
+                .map(|level| {
+                    let mut level_entries = vec![None; slot_count];
+                    level_entries[level] = Some(Entry {
+                        table: Permutation::identity(slot_count),
+                        word: Vec::new(),
+                    });
+                    level_entries
+                })
+                .collect(),
+            entry_count: slot_count,
+        }
+    }
+
+    /// Number of reachable states, saturating at `u128::MAX`.
+    pub fn state_count(&self) -> u128 {
+        self.entries
+            .iter()
+            .map(|level| level.iter().flatten().count() as u128)
+            .fold(1, u128::saturating_mul)
+    }
+
+    /// Words of a uniformly random reachable state, as indices into the move
+    /// tables; applying them to the solved puzzle reaches the state.
+    pub fn random_word(&self, rng: &mut impl Rng) -> Vec<usize> {
+        let mut word = Vec::new();
+        for level in self.entries.iter().rev() {
+            let choices: Vec<&Entry> = level.iter().flatten().collect();
+            word.extend_from_slice(&choices[rng.gen_range(0..choices.len())].word);
+        }
+        self.reduce(word)
+    }
+
+    /// Sifts every state reachable in `SHORT_WORD_LENGTH` moves, so that the
+    /// first levels get the shortest possible words.
+    fn add_short_words(&mut self) {
+        let slot_count = self.entries.len();
+        let mut seen = HashSet::from([Permutation::identity(slot_count)]);
+        let mut frontier = vec![Entry {
+            table: Permutation::identity(slot_count),
+            word: Vec::new(),
+        }];
+        for _ in 0..SHORT_WORD_LENGTH {
+            let mut next = Vec::new();
+            for entry in &frontier {
+                for (i, table) in self.tables.iter().enumerate() {
+                    let reached = entry.table.compose(table);
+                    if seen.insert(reached.clone()) {
+                        let mut word = entry.word.clone();
+                        word.push(i);
+                        next.push(Entry {
+                            table: reached,
+                            word,
+                        });
+                    }
+                }
+            }
+            for entry in &next {
+                self.sift(entry.clone(), 0);
+            }
+            frontier = next;
+        }
+    }
+
+    /// Sifts the Schreier generators of every level until all of them sift
+    /// through, at which point the chain holds every reachable state, or until
+    /// it has `complete_count` entries.
+    fn close(&mut self, complete_count: usize) {
+        while self.entry_count < complete_count {
+            let mut changed = false;
+            for level in 0..self.entries.len() {
+                let generators: Vec<Entry> = self.generators_fixing(level);
+                let representatives: Vec<Entry> =
+                    self.entries[level].iter().flatten().cloned().collect();
+                for representative in &representatives {
+                    for generator in &generators {
+                        let mut word = representative.word.clone();
+                        word.extend_from_slice(&generator.word);
+                        let product = Entry {
+                            table: representative.table.compose(&generator.table),
+                            word: self.reduce(word),
+                        };
+                        changed |= self.sift(product, level);
+                        if self.entry_count == complete_count {
+                            return;
+                        }
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    /// Sifts the products of every two entries of a level and of one with the
+    /// inverse of another, which often finds shorter words for the levels
+    /// below.
+    fn improve(&mut self) {
+        for level in 0..self.entries.len() {
+            let entries: Vec<Entry> = self.entries[level].iter().flatten().cloned().collect();
+            for first in &entries {
+                for second in &entries {
+                    let mut product = first.word.clone();
+                    product.extend_from_slice(&second.word);
+                    let mut quotient = first.word.clone();
+                    for &mv in second.word.iter().rev() {
+                        quotient.extend_from_slice(&self.inverses[mv]);
+                    }
+                    for (table, word) in [
+                        (first.table.compose(&second.table), product),
+                        (first.table.compose(&second.table.inverse()), quotient),
+                    ] {
+                        let word = self.reduce(word);
+                        self.sift(Entry { table, word }, level);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The moves and entries that keep the pieces of slots `0..level`.
+    fn generators_fixing(&self, level: usize) -> Vec<Entry> {
+        let fixes = |table: &Permutation| (0..level).all(|slot| table.0[slot] as usize == slot);
+        let moves = self
+            .tables
+            .iter()
+            .enumerate()
+            .filter(|(_, table)| fixes(table))
+            .map(|(i, table)| Entry {
+                table: table.clone(),
+                word: if self.with_words { vec![i] } else { Vec::new() },
+            });
+        let entries = self.entries[level..]
+            .iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| !entry.table.is_identity())
+            .cloned();
+        moves.chain(entries).collect()
+    }
+
+    /// Divides `element` by the entries of the levels from `level` on, adding
+    /// it where a level has no entry for it yet and swapping it in where its
+    /// word is shorter. Returns whether the chain changed.
+    fn sift(&mut self, mut element: Entry, level: usize) -> bool {
+        let slot_count = self.entries.len();
+        let mut changed = false;
+        for level in level..slot_count {
+            if element.table.is_identity() {
+                break;
+            }
+            let slot = element
+                .table
+                .0
+                .iter()
+                .position(|&piece| piece as usize == level)
+                .expect("tables are permutations");
+            let entry = match &mut self.entries[level][slot] {
+                Some(entry) => entry,
+                empty => {
+                    *empty = Some(element);
+                    self.entry_count += 1;
+                    return true;
+                }
+            };
+            if element.word.len() < entry.word.len() {
+                std::mem::swap(entry, &mut element);
+                changed = true;
+            }
+            let entry = self.entries[level][slot].as_ref().expect("entry was set");
+            let mut word = element.word;
+            for &mv in entry.word.iter().rev().filter(|_| self.with_words) {
+                word.extend_from_slice(&self.inverses[mv]);
+            }
+            element = Entry {
+                table: element.table.compose(&entry.table.inverse()),
+                word: self.reduce(word),
+            };
+        }
+        changed
+    }
+
+    /// Cancels moves followed by their inverse and shortens runs of a move
+    /// to at most half its order, turning the other way if that is shorter.
+    fn reduce(&self, word: Vec<usize>) -> Vec<usize> {
+        let mut reduced: Vec<usize> = Vec::with_capacity(word.len());
+        for mv in word {
+            if self.inverses[mv].len() == 1 && reduced.last() == Some(&self.inverses[mv][0]) {
+                reduced.pop();
+                continue;
+            }
+            reduced.push(mv);
+            let run = reduced.iter().rev().take_while(|&&last| last == mv).count();
+            if run == self.orders[mv] {
+                reduced.truncate(reduced.len() - run);
+            }
+        }
+        let mut shortened = Vec::with_capacity(reduced.len());
+        let mut i = 0;
+        while i < reduced.len() {
+            let mv = reduced[i];
+            let run = reduced[i..].iter().take_while(|&&next| next == mv).count();
+            match self.inverses[mv][..] {
+                [undo] if run > self.orders[mv] / 2 => {
+                    shortened.extend(std::iter::repeat_n(undo, self.orders[mv] - run))
+                }
+                _ => shortened.extend(std::iter::repeat_n(mv, run)),
+            }
+            i += run;
+        }
+        shortened
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::puzzle_trait::PuzzleTrait;
+    use crate::single_puzzle::{builtin_move_tables, SinglePuzzle};
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use std::sync::OnceLock;
+
+    /// Building a chain takes a while, so every test shares one per config.
+    fn builtin_chain(with_opposite_move: bool) -> &'static StabilizerChain {
+        static CHAINS: [OnceLock<StabilizerChain>; 2] = [OnceLock::new(), OnceLock::new()];
+        CHAINS[with_opposite_move as usize]
+            .get_or_init(|| StabilizerChain::new(builtin_move_tables(with_opposite_move)).unwrap())
+    }
+
+    fn factorial(n: u128) -> u128 {
+        (1..=n).product()
+    }
+
+    /// Whether `table` is a product of one entry per level of `chain`.
+    fn contains(chain: &StabilizerChain, table: &Permutation) -> bool {
+        let mut table = table.clone();
+        for (level, entries) in chain.entries.iter().enumerate() {
+            let slot = table
+                .0
+                .iter()
+                .position(|&piece| piece as usize == level)
+                .unwrap();
+            let Some(entry) = &entries[slot] else {
+                return false;
+            };
+            table = table.compose(&entry.table.inverse());
+        }
+        table.is_identity()
+    }
+
+    #[test]
+    fn counts_every_permutation_of_the_builtin_puzzle() {
+        // six-slot cycles are odd, so the plain faces reach every permutation
+        assert_eq!(builtin_chain(false).state_count(), factorial(24));
+    }
+
+    #[test]
+    fn counts_the_even_permutations_with_opposite_moves() {
+        // every move turns two faces, so only even permutations are reached
+        assert_eq!(builtin_chain(true).state_count(), factorial(24) / 2);
+    }
+
+    #[test]
+    fn random_words_replay_to_reachable_states() {
+        for with_opposite_move in [false, true] {
+            let tables = builtin_move_tables(with_opposite_move);
+            let chain = builtin_chain(with_opposite_move);
+            let mut rng = ChaCha8Rng::seed_from_u64(7);
+            let moves = SinglePuzzle::all_moves(&with_opposite_move);
+            for _ in 0..20 {
+                let word = chain.random_word(&mut rng);
+                let reached = word.iter().fold(Permutation::identity(24), |table, &mv| {
+                    table.compose(&tables[mv])
+                });
+                assert!(contains(chain, &reached));
+                let mut puzzle = SinglePuzzle::solved(&with_opposite_move);
+                for &mv in &word {
+                    puzzle.apply_move(moves[mv], &with_opposite_move);
+                }
+                assert_eq!(puzzle, SinglePuzzle::from_slots(reached.0).unwrap());
+            }
+        }
+    }
+}
@@ -0,0 +1,331 @@
+use crate::canonical::MoveRules;
+use crate::error::SolverError;
+use crate::external_sort::{RunReader, SortedCursor};
+use crate::puzzle_trait::PuzzleTrait;
+use crate::reachable::ReachableStates;
+use crate::record::Record;
+use std::fs::{read_to_string, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Bumped whenever the layout of a table directory or its records changes.
+pub const TABLE_VERSION: u32 = 1;
+const TABLE_META: &str = "table.meta";
+const TABLE_INDEX: &str = "index.bin";
+
+/// What `table.meta` records besides the layers; a table only opens for a
+/// solve that would have built the same states.
+struct TableHeader {
+    puzzle: String,
+    config: String,
+    num_score_weakens: Option<usize>,
+    improve: bool,
+}
+
+impl TableHeader {
+    fn of<Puzzle: PuzzleTrait>(states: &ReachableStates<Puzzle>) -> Self {
+        Self {
+            puzzle: Puzzle::TABLE_ID.to_string(),
+            config: Puzzle::encode_config(&states.config),
+            num_score_weakens: states.num_score_weakens,
+            improve: states.improve,
+        }
+    }
+}
+
+impl<Puzzle: PuzzleTrait> ReachableStates<Puzzle> {
+    /// Writes `table.meta` and a per-layer index next to the layers, so the
+    /// store directory can be opened again with `open_table`.
+    ///
+    /// `table.meta` is a line-based text file:
+    ///
+    /// ```text
+    /// cube-solver table 1
+    /// puzzle slots
+    /// config plain
+    /// score_weakens none
+    /// improve false
+    /// layer 0 1 layer_0/states_0.bin
+    /// layer 1 12 layer_1/states_0.bin
+    /// ```
+    ///
+    /// Each `layer` line holds the layer, its number of states and its files
+    /// relative to the table directory. `layer_N/index.bin` holds the first
+    /// record of every file of the layer.
+    pub fn save_table(&self) -> Result<(), SolverError> {
+        let header = TableHeader::of(self);
+        let mut meta = format!(
+            "cube-solver table {}\npuzzle {}\nconfig {}\nscore_weakens {}\nimprove {}\n",
+            TABLE_VERSION,
+            header.puzzle,
+            header.config,
+            header
+                .num_score_weakens
+                .map_or("none".to_string(), |k| k.to_string()),
+            header.improve
+        );
+        let prefix = format!("{}/", self.store_directory);
+        for (layer, files) in self.layers.iter().enumerate() {
+            meta.push_str(&format!("layer {} {}", layer, self.layer_sizes[layer]));
+            for file in files {
+                meta.push(' ');
+                meta.push_str(file.strip_prefix(&prefix).unwrap_or(file));
+            }
+            meta.push('\n');
+            let index_path = format!("{}layer_{}/{}", prefix, layer, TABLE_INDEX);
+            let mut writer = BufWriter::new(File::create(index_path)?);
+            for file in files {
+                if let Some(first) = RunReader::<Puzzle>::open(file, &self.config)?.next_record()? {
+                    first.save_binary_to_file(&mut writer)?;
+                }
+            }
+            writer.flush()?;
+        }
+        std::fs::write(format!("{}{}", prefix, TABLE_META), meta)?;
+        Ok(())
+    }
+
+    /// Opens a table written by `save_table` without changing it. `root` has
+    /// to be the puzzle the table was built from, `config` its config, and
+    /// `num_score_weakens` and `improve` have to match the build.
+    pub fn open_table(
+        directory: &str,
+        root: Puzzle,
+        config: Puzzle::Config,
+        num_score_weakens: Option<usize>,
+        improve: bool,
+    ) -> Result<Self, SolverError> {
+        let meta_path = format!("{}/{}", directory, TABLE_META);
+        let meta = read_to_string(&meta_path).map_err(|err| {
+            SolverError::InvalidTable(format!("can not read {}: {}", meta_path, err))
+        })?;
+        let mut lines = meta.lines();
+        let version = lines
+            .next()
+            .and_then(|line| line.strip_prefix("cube-solver table "))
+            .ok_or_else(|| {
+                SolverError::InvalidTable(format!("{} is not a table description", meta_path))
+            })?;
+        if version != TABLE_VERSION.to_string() {
+            return Err(SolverError::InvalidTable(format!(
+                "{} has version {}, expected {}",
+                meta_path, version, TABLE_VERSION
+            )));
+        }
+        let mut states = Self {
+            batch_size: 0,
+            merge_fan_in: 0,
+            num_score_weakens,
+            improve,
+            layers: Vec::new(),
+            layer_sizes: Vec::new(),
+            nodes_expanded: 0,
+            store_directory: directory.to_string(),
+            read_only: true,
+            index: Vec::new(),
+            all_moves: Puzzle::all_moves(&config),
+            config,
+            rules: MoveRules::unrestricted(0),
+            root,
+        };
+        let expected = TableHeader::of(&states);
+        let mut header = TableHeader {
+            puzzle: String::new(),
+            config: String::new(),
+            num_score_weakens: None,
+            improve: false,
+        };
+        for (number, line) in lines.enumerate() {
+            let invalid = |reason: &str| {
+                SolverError::InvalidTable(format!("{}: line {}: {}", meta_path, number + 2, reason))
+            };
+            let (key, value) = line
+                .split_once(' ')
+                .ok_or_else(|| invalid("missing value"))?;
+            match key {
+                "puzzle" => header.puzzle = value.to_string(),
+                "config" => header.config = value.to_string(),
+                "score_weakens" => {
+                    header.num_score_weakens = match value {
+                        "none" => None,
+                        k => Some(k.parse().map_err(|_| invalid("expected a number"))?),
+                    }
+                }
+                "improve" => {
+                    header.improve = value.parse().map_err(|_| invalid("expected a bool"))?
+                }
+                "layer" => {
+                    let mut fields = value.split(' ');
+                    let layer: usize = fields
+                        .next()
+                        .and_then(|field| field.parse().ok())
+                        .ok_or_else(|| invalid("expected a layer number"))?;
+                    if layer != states.layers.len() {
+                        return Err(invalid("layers out of order"));
+                    }
+                    let size: usize = fields
+                        .next()
+                        .and_then(|field| field.parse().ok())
+                        .ok_or_else(|| invalid("expected a state count"))?;
+                    let files: Vec<String> = fields
+                        .map(|file| format!("{}/{}", directory, file))
+                        .collect();
+                    if let Some(missing) = files.iter().find(|file| !Path::new(file).is_file()) {
+                        return Err(invalid(&format!("{} is missing", missing)));
+                    }
+                    states.layers.push(files);
+                    states.layer_sizes.push(size);
+                }
+                _ => return Err(invalid(&format!("unknown key {}", key))),
+            }
+        }
+        for (what, found, wanted) in [
+            ("puzzle", &header.puzzle, &expected.puzzle),
+            ("config", &header.config, &expected.config),
+        ] {
+            if found != wanted {
+                return Err(SolverError::InvalidTable(format!(
+                    "{} was built for {} {}, not {}",
+                    directory, what, found, wanted
+                )));
+            }
+        }
+        if header.num_score_weakens != expected.num_score_weakens
+            || header.improve != expected.improve
+        {
+            return Err(SolverError::InvalidTable(format!(
+                "{} was built with score pruning {:?} (improve {}), not {:?} (improve {})",
+                directory,
+                header.num_score_weakens,
+                header.improve,
+                expected.num_score_weakens,
+                expected.improve
+            )));
+        }
+        if states.layers.is_empty() {
+            return Err(SolverError::InvalidTable(format!(
+                "{} has no layers",
+                meta_path
+            )));
+        }
+        for layer in 0..states.layers.len() {
+            let index_path = format!("{}/layer_{}/{}", directory, layer, TABLE_INDEX);
+            let mut reader = RunReader::<Puzzle>::open(&index_path, &states.config)?;
+            let mut firsts = Vec::with_capacity(states.layers[layer].len());
+            while let Some(first) = reader.next_record()? {
+                firsts.push(first.state);
+            }
+            if firsts.len() != states.layers[layer].len() {
+                return Err(SolverError::InvalidTable(format!(
+                    "{} lists {} files, layer {} has {}",
+                    index_path,
+                    firsts.len(),
+                    layer,
+                    states.layers[layer].len()
+                )));
+            }
+            states.index.push(firsts);
+        }
+        Ok(states)
+    }
+
+    /// Finds `state` in a table through the index, reading one file per layer
+    /// at most. Returns the layer holding it and the record stored for it.
+    pub fn lookup(&self, state: &Puzzle) -> Result<Option<(usize, Record<Puzzle>)>, SolverError> {
+        for (layer, firsts) in self.index.iter().enumerate() {
+            let file = firsts.partition_point(|first| first <= state);
+            if file == 0 {
+                continue;
+            }
+            let path = &self.layers[layer][file - 1];
+            let mut cursor =
+                SortedCursor::<Puzzle>::open(std::slice::from_ref(path), &self.config)?;
+            while cursor.peek().is_some_and(|stored| stored.state < *state) {
+                cursor.advance()?;
+            }
+            if cursor.peek().is_some_and(|stored| stored.state == *state) {
+                return Ok(cursor.advance()?.map(|record| (layer, record)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::SolverError;
+    use crate::puzzle_trait::PuzzleTrait;
+    use crate::reachable::ReachableStates;
+    use crate::single_puzzle::{Direction, Face, Move, SinglePuzzle};
+    use crate::solver::{SolveOptions, Solver};
+
+    /// An exact table of the plain builtin puzzle, 3 moves deep, in a fresh directory.
+    fn build(name: &str) -> String {
+        let directory = std::env::temp_dir()
+            .join(format!("cube-solver-{}-{}", name, std::process::id()))
+            .to_string_lossy()
+            .into_owned();
+        std::fs::remove_dir_all(&directory).ok();
+        let options = SolveOptions {
+            batch_size: 50,
+            exact: true,
+            quiet: true,
+            ..SolveOptions::default()
+        };
+        Solver::build_table::<SinglePuzzle>(&false, 3, &directory, &options).unwrap();
+        directory
+    }
+
+    fn open(
+        directory: &str,
+        with_opposite_move: bool,
+    ) -> Result<ReachableStates<SinglePuzzle>, SolverError> {
+        ReachableStates::open_table(
+            directory,
+            SinglePuzzle::new_solved(),
+            with_opposite_move,
+            None,
+            false,
+        )
+    }
+
+    #[test]
+    fn a_saved_table_opens_and_finds_its_states() {
+        let directory = build("table-lookup");
+        let table = open(&directory, false).unwrap();
+        assert_eq!(table.layer_sizes, [1, 12, 90, 562]);
+        let mut state = SinglePuzzle::new_solved();
+        for face in [Face::Left, Face::Right] {
+            state.apply_move(Move::new(face, Direction::Clockwise), &false);
+        }
+        let (layer, record) = table.lookup(&state).unwrap().unwrap();
+        assert_eq!(layer, 2);
+        assert_eq!(record.state, state);
+        let mut replayed = SinglePuzzle::new_solved();
+        replayed.apply_scramble(record.scramble(&false).unwrap(), &false);
+        assert_eq!(replayed, state);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn a_table_of_another_version_or_config_is_rejected() {
+        let directory = build("table-header");
+        assert!(matches!(
+            open(&directory, true),
+            Err(SolverError::InvalidTable(message)) if message.contains("config")
+        ));
+        let meta_path = format!("{}/{}", directory, super::TABLE_META);
+        let meta = std::fs::read_to_string(&meta_path).unwrap();
+        let old = meta.replacen(
+            &format!("cube-solver table {}", super::TABLE_VERSION),
+            "cube-solver table 0",
+            1,
+        );
+        std::fs::write(&meta_path, old).unwrap();
+        assert!(matches!(
+            open(&directory, false),
+            Err(SolverError::InvalidTable(message)) if message.contains("version 0")
+        ));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
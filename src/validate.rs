@@ -0,0 +1,322 @@
+use crate::puzzle_spec::PuzzleSpec;
+use std::fmt;
+
+/// The slots a face move leaves behind when applied to the solved puzzle:
+/// `clockwise[i]` is the slot whose piece ends up in slot `i`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaceTable {
+    pub name: String,
+    pub clockwise: Vec<u8>,
+    pub counter_clockwise: Vec<u8>,
+    /// Index of the face turned alongside this one when opposite moves are enabled.
+    pub opposite: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationIssue {
+    /// The move overwrites some pieces and copies others twice.
+    NotABijection {
+        mv: String,
+        lost: Vec<u8>,
+        duplicated: Vec<u8>,
+    },
+    /// The move permutes the slots, but not as a single cycle of the expected length.
+    WrongCycle { mv: String, cycles: Vec<Vec<u8>> },
+    /// Clockwise followed by counter clockwise leaves these slots displaced.
+    InverseNotIdentity { face: String, slots: Vec<u8> },
+    /// `a` names `b` as its opposite, but `b` names `back` (or nothing).
+    OppositeMismatch {
+        a: String,
+        b: String,
+        back: Option<String>,
+    },
+    /// A face is its own opposite, or shares slots with its opposite.
+    OppositeOverlap {
+        a: String,
+        b: String,
+        slots: Vec<u8>,
+    },
+    /// `slot` lists `neighbour`, but not the other way around.
+    AsymmetricNeighbours { slot: u8, neighbour: u8 },
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationIssue::NotABijection {
+                mv,
+                lost,
+                duplicated,
+            } => write!(
+                f,
+                "{} is not a bijection: pieces from slots {:?} are lost, pieces from slots {:?} are duplicated",
+                mv, lost, duplicated
+            ),
+            ValidationIssue::WrongCycle { mv, cycles } => {
+                write!(f, "{} is not a single cycle: moves slots in cycles {:?}", mv, cycles)
+            }
+            ValidationIssue::InverseNotIdentity { face, slots } => write!(
+                f,
+                "{} CW followed by {} CCW is not the identity: slots {:?} are displaced",
+                face, face, slots
+            ),
+            ValidationIssue::OppositeMismatch { a, b, back } => match back {
+                Some(back) => write!(
+                    f,
+                    "opposite of {} is {}, but opposite of {} is {}",
+                    a, b, b, back
+                ),
+                None => write!(f, "opposite of {} is {}, but {} has no opposite", a, b, b),
+            },
+            ValidationIssue::OppositeOverlap { a, b, slots } => write!(
+                f,
+                "opposite faces {} and {} share slots {:?}",
+                a, b, slots
+            ),
+            ValidationIssue::AsymmetricNeighbours { slot, neighbour } => write!(
+                f,
+                "slot {} lists {} as a neighbour, but slot {} does not list {}",
+                slot, neighbour, neighbour, slot
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.issues.is_empty() {
+            return writeln!(f, "puzzle definition is valid");
+        }
+        for issue in &self.issues {
+            writeln!(f, "{}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks move tables and the neighbour graph of a puzzle with `slot_count` slots.
+///
+/// With `cycle_len` set, every move must additionally be a single cycle of that length.
+pub fn validate_tables(
+    slot_count: usize,
+    faces: &[FaceTable],
+    neighbours: &[Vec<u8>],
+    cycle_len: Option<usize>,
+) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    for face in faces {
+        let mut bijective = true;
+        for (direction, table) in [("CW", &face.clockwise), ("CCW", &face.counter_clockwise)] {
+            let mv = format!("{} {}", face.name, direction);
+            let mut counts = vec![0usize; slot_count];
+            for &slot in table.iter() {
+                counts[slot as usize] += 1;
+            }
+            let lost: Vec<u8> = (0..slot_count as u8)
+                .filter(|&slot| counts[slot as usize] == 0)
+                .collect();
+            if !lost.is_empty() {
+                let duplicated = (0..slot_count as u8)
+                    .filter(|&slot| counts[slot as usize] > 1)
+                    .collect();
+                report.issues.push(ValidationIssue::NotABijection {
+                    mv,
+                    lost,
+                    duplicated,
+                });
+                bijective = false;
+                continue;
+            }
+            let cycles = cycles_of(table);
+            let single_cycle =
+                cycles.len() == 1 && cycle_len.is_none_or(|cycle_len| cycles[0].len() == cycle_len);
+            if !single_cycle {
+                report
+                    .issues
+                    .push(ValidationIssue::WrongCycle { mv, cycles });
+            }
+        }
+        if bijective {
+            // turning CW then CCW leaves clockwise[counter_clockwise[i]] in slot i
+            let displaced: Vec<u8> = (0..slot_count)
+                .filter(|&i| face.clockwise[face.counter_clockwise[i] as usize] as usize != i)
+                .map(|i| i as u8)
+                .collect();
+            if !displaced.is_empty() {
+                report.issues.push(ValidationIssue::InverseNotIdentity {
+                    face: face.name.clone(),
+                    slots: displaced,
+                });
+            }
+        }
+    }
+    for face in faces {
+        let Some(opposite) = face.opposite else {
+            continue;
+        };
+        let other = &faces[opposite];
+        match other.opposite {
+            Some(back) if faces[back].name == face.name => {}
+            back => report.issues.push(ValidationIssue::OppositeMismatch {
+                a: face.name.clone(),
+                b: other.name.clone(),
+                back: back.map(|back| faces[back].name.clone()),
+            }),
+        }
+        if face.name <= other.name {
+            let moved = |face: &FaceTable| -> Vec<u8> {
+                (0..slot_count)
+                    .filter(|&i| face.clockwise[i] as usize != i)
+                    .map(|i| i as u8)
+                    .collect()
+            };
+            let other_moved = moved(other);
+            let shared: Vec<u8> = moved(face)
+                .into_iter()
+                .filter(|slot| other_moved.contains(slot))
+                .collect();
+            if face.name == other.name || !shared.is_empty() {
+                report.issues.push(ValidationIssue::OppositeOverlap {
+                    a: face.name.clone(),
+                    b: other.name.clone(),
+                    slots: shared,
+                });
+            }
+        }
+    }
+    for (slot, slot_neighbours) in neighbours.iter().enumerate() {
+        for &neighbour in slot_neighbours {
+            if !neighbours[neighbour as usize].contains(&(slot as u8)) {
+                report.issues.push(ValidationIssue::AsymmetricNeighbours {
+                    slot: slot as u8,
+                    neighbour,
+                });
+            }
+        }
+    }
+    report
+}
+
+/// Non-trivial cycles of the permutation `table`, each starting at its smallest slot.
+fn cycles_of(table: &[u8]) -> Vec<Vec<u8>> {
+    let mut visited = vec![false; table.len()];
+    let mut cycles = Vec::new();
+    for start in 0..table.len() {
+        if visited[start] || table[start] as usize == start {
+            continue;
+        }
+        let mut cycle = Vec::new();
+        let mut slot = start;
+        while !visited[slot] {
+            visited[slot] = true;
+            cycle.push(slot as u8);
+            slot = table[slot] as usize;
+        }
+        cycles.push(cycle);
+    }
+    cycles
+}
+
+pub fn validate_spec(spec: &PuzzleSpec) -> ValidationReport {
+    let faces: Vec<FaceTable> = spec
+        .faces
+        .iter()
+        .enumerate()
+        .map(|(i, face)| {
+            let mut clockwise: Vec<u8> = (0..spec.slot_count as u8).collect();
+            let mut counter_clockwise = clockwise.clone();
+            let len = face.cycle.len();
+            for j in 0..len {
+                clockwise[face.cycle[(j + 1) % len] as usize] = face.cycle[j];
+                counter_clockwise[face.cycle[j] as usize] = face.cycle[(j + 1) % len];
+            }
+            FaceTable {
+                name: face.name.clone(),
+                clockwise,
+                counter_clockwise,
+                opposite: spec.opposite_face(i),
+            }
+        })
+        .collect();
+    validate_tables(spec.slot_count, &faces, &spec.neighbours, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SLOT_COUNT: usize = 6;
+
+    fn face(clockwise: [u8; SLOT_COUNT], counter_clockwise: [u8; SLOT_COUNT]) -> FaceTable {
+        FaceTable {
+            name: "A".to_string(),
+            clockwise: clockwise.to_vec(),
+            counter_clockwise: counter_clockwise.to_vec(),
+            opposite: None,
+        }
+    }
+
+    fn issues(face: FaceTable, cycle_len: Option<usize>) -> Vec<ValidationIssue> {
+        validate_tables(
+            SLOT_COUNT,
+            &[face],
+            &vec![Vec::new(); SLOT_COUNT],
+            cycle_len,
+        )
+        .issues
+    }
+
+    #[test]
+    fn two_swaps_are_not_a_six_cycle() {
+        let swaps = [1, 0, 3, 2, 4, 5];
+        let cycles = vec![vec![0, 1], vec![2, 3]];
+        assert_eq!(
+            issues(face(swaps, swaps), Some(6)),
+            [
+                ValidationIssue::WrongCycle {
+                    mv: "A CW".to_string(),
+                    cycles: cycles.clone(),
+                },
+                ValidationIssue::WrongCycle {
+                    mv: "A CCW".to_string(),
+                    cycles,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn a_copied_slot_is_not_a_bijection() {
+        assert_eq!(
+            issues(face([1, 1, 2, 3, 4, 5], [1, 0, 2, 3, 4, 5]), None),
+            [ValidationIssue::NotABijection {
+                mv: "A CW".to_string(),
+                lost: vec![0],
+                duplicated: vec![1],
+            }]
+        );
+    }
+
+    #[test]
+    fn turning_back_the_same_way_is_not_the_identity() {
+        let three_cycle = [2, 0, 1, 3, 4, 5];
+        assert_eq!(
+            issues(face(three_cycle, three_cycle), None),
+            [ValidationIssue::InverseNotIdentity {
+                face: "A".to_string(),
+                slots: vec![0, 1, 2],
+            }]
+        );
+    }
+}